@@ -1,8 +1,16 @@
 use super::position::AugmentedPos;
 use super::position::Position;
 use super::prelude::*;
+#[cfg(feature = "perft")]
+use super::tt::{PerftCache, PerftInfo};
 
 impl Position {
+    // count leaf nodes at the given depth, without printing anything
+    pub fn perft(&self, depth: usize) -> usize {
+        self.perft_rec(depth, 0)
+    }
+
+    // UCI facing perft, prints the node count of each root move (divide)
     #[cfg(feature = "perft")]
     pub fn perft_top<O: UciOutputStream>(&mut self, depth: usize) -> usize {
         use crate::uci::UciResponse;
@@ -10,10 +18,10 @@ impl Position {
         match depth {
             0 => 1,
             _ => {
-                let sum = AugmentedPos::map_issues(
+                let sum = AugmentedPos::map_issues_mut(
                     self,
                     |pos, mbv| {
-                        let partial_sum = Self::perft_rec(pos, depth - 1, 0);
+                        let partial_sum = pos.perft_in_place(depth - 1);
                         O::send_response(UciResponse::Raw(
                             format!("{mbv}: {}", partial_sum).as_str(),
                         ))
@@ -31,6 +39,124 @@ impl Position {
         }
     }
 
+    // same as perft_top, the counts of transposed subtrees being shared through the cache
+    #[cfg(feature = "perft")]
+    pub fn perft_top_cached<O: UciOutputStream>(
+        &mut self,
+        depth: usize,
+        cache: &mut PerftCache,
+    ) -> usize {
+        use crate::uci::UciResponse;
+
+        match depth {
+            0 => 1,
+            _ => AugmentedPos::map_issues_mut(
+                self,
+                |pos, mbv| {
+                    let partial_sum = pos.perft_cached(depth - 1, cache);
+                    O::send_response(UciResponse::Raw(format!("{mbv}: {}", partial_sum).as_str()))
+                        .unwrap();
+                    partial_sum
+                },
+                |a, b| a + b,
+            )
+            .unwrap_or(0),
+        }
+    }
+
+    // same as perft_top, the subtrees of the root moves being counted on up to threads threads
+    // the counts of the root moves are printed once all are known, in the order of perft_top
+    #[cfg(feature = "perft")]
+    pub fn perft_top_parallel<O: UciOutputStream>(
+        &mut self,
+        depth: usize,
+        threads: usize,
+    ) -> usize {
+        use crate::uci::UciResponse;
+
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        let threads = threads.clamp(1, moves.len().max(1));
+        // thread i takes the moves i, i + threads, ...
+        let counts: Vec<Vec<(usize, usize)>> = std::thread::scope(|s| {
+            let workers: Vec<_> = (0..threads)
+                .map(|i| {
+                    let (root, moves) = (*self, &moves);
+                    s.spawn(move || {
+                        (i..moves.len())
+                            .step_by(threads)
+                            .map(|j| {
+                                let mut pos = root;
+                                pos.stack(&moves[j], moves[j].promotion());
+                                (j, pos.perft_in_place(depth - 1))
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        let mut counts: Vec<(usize, usize)> = counts.into_iter().flatten().collect();
+        counts.sort_unstable();
+        for (j, partial_sum) in &counts {
+            O::send_response(UciResponse::Raw(
+                format!("{}: {partial_sum}", moves[*j]).as_str(),
+            ))
+            .unwrap();
+        }
+        counts.iter().map(|(_, n)| n).sum()
+    }
+
+    #[cfg(feature = "perft")]
+    fn perft_cached(&mut self, depth: usize, cache: &mut PerftCache) -> usize {
+        // leaves are counted faster than looked up
+        if depth <= 1 {
+            return self.perft_in_place(depth);
+        }
+        // the fifty-move counter does not change the count, transpositions share their entry
+        let mut key = *self;
+        key.fifty_mv = 0;
+        // a count is only valid at its own depth
+        if let Some(info) = cache[&key]
+            && info.depth as usize == depth
+        {
+            return info.nodes as usize;
+        }
+        let nodes = AugmentedPos::map_issues_mut(
+            self,
+            |pos, _| pos.perft_cached(depth - 1, cache),
+            |a, b| a + b,
+        )
+        .unwrap_or(0);
+        // counts too large for an entry are not stored
+        if let Ok(n) = u32::try_from(nodes) {
+            cache.push(
+                &key,
+                &PerftInfo {
+                    nodes: n,
+                    depth: depth as u32,
+                },
+            );
+        }
+        nodes
+    }
+
+    // same count as perft, the moves being played on self (stack/unstack) rather than on copies
+    pub fn perft_in_place(&mut self, depth: usize) -> usize {
+        match depth {
+            0 => 1,
+            1 => AugmentedPos::map_issues_mut(self, |_, _| 1, |a, b| a + b).unwrap_or(0),
+            _ => AugmentedPos::map_issues_mut(
+                self,
+                |pos, _| pos.perft_in_place(depth - 1),
+                |a, b| a + b,
+            )
+            .unwrap_or(0),
+        }
+    }
+
     fn perft_rec(&self, depth: usize, depth_in: usize) -> usize {
         match depth {
             0 => 1,
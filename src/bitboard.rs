@@ -15,6 +15,7 @@
 //! - `ToBB`: Convert self into a `Bitboard<Self>`
 //!
 
+use crate::player::Player;
 use std::fmt::{Debug, Display};
 
 #[allow(non_camel_case_types)]
@@ -61,6 +62,12 @@ impl<T: BitboardSpec> PartialEq for Bitboard<T> {
         self.to_bb64() == x.to_bb64()
     }
 }
+impl<T: BitboardSpec> Eq for Bitboard<T> {}
+impl<T: BitboardSpec> std::hash::Hash for Bitboard<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.to_bb64().hash(state)
+    }
+}
 
 /*
 impl<T: ToBB64> ToBB64 for &T {
@@ -147,6 +154,8 @@ impl ToBB64 for File {
     }
 }
 
+// one square shifts: up/down (lsu/lsd) drop the bits leaving the board through rank 8/1,
+// left/right (lsl/lsr) mask out the bits that would wrap around to the other side of the board
 pub trait BitboardFastOps: BitboardSpec {
     #[inline(always)]
     fn lsu(&self) -> Bitboard<GenericBB> {
@@ -188,6 +197,12 @@ pub trait BitboardFastOps: BitboardSpec {
     fn fn_bitnot(&self) -> Bitboard<GenericBB> {
         Bitboard(GenericBB(!self.to_bb64()))
     }
+
+    // number of squares set, a single popcount instead of iterating them
+    #[inline(always)]
+    fn count(&self) -> u32 {
+        self.to_bb64().count_ones()
+    }
 }
 
 impl<U: BitboardSpec> BitboardFastOps for U {}
@@ -293,6 +308,8 @@ impl<T: BitboardSpec> std::ops::Not for Bitboard<T> {
         self.fn_bitnot()
     }
 }
+// `bb << n` moves every square n files towards file A, `bb >> n` towards file H,
+// squares going past the edge of the board are dropped, never wrapped to the next rank
 impl<T: BitboardSpec> std::ops::Shl<usize> for Bitboard<T> {
     type Output = Bitboard<GenericBB>;
     #[inline(always)]
@@ -349,6 +366,8 @@ impl<T: BitboardSpec> std::ops::Shr<usize> for Bitboard<T> {
     }
 }
 
+// `bb + n` moves every square n ranks up (towards rank 8), `bb - n` n ranks down,
+// squares going past rank 8 or rank 1 are dropped: see shift_north/shift_south
 impl<T: BitboardSpec> std::ops::Add<usize> for Bitboard<T> {
     type Output = Bitboard<GenericBB>;
     #[inline(always)]
@@ -373,11 +392,84 @@ impl<T: BitboardSpec> std::ops::Sub<usize> for Bitboard<T> {
     }
 }
 
+// pawn spans, using Kogge-Stone shifts
+impl Bitboard<GenericBB> {
+    // squares moved the given number of ranks up, those pushed past rank 8 are lost
+    // shifting by 8 ranks or more leaves the board empty
+    pub const fn shift_north(self, ranks: u32) -> Bitboard<GenericBB> {
+        match self.0.0.checked_shl(8 * ranks) {
+            Some(x) if ranks < 8 => Bitboard(GenericBB(x)),
+            _ => Bitboard(GenericBB(0)),
+        }
+    }
+    // squares moved the given number of ranks down, those pushed past rank 1 are lost
+    pub const fn shift_south(self, ranks: u32) -> Bitboard<GenericBB> {
+        match self.0.0.checked_shr(8 * ranks) {
+            Some(x) if ranks < 8 => Bitboard(GenericBB(x)),
+            _ => Bitboard(GenericBB(0)),
+        }
+    }
+    // every square on or above a set square, on the same file
+    pub const fn north_fill(self) -> Bitboard<GenericBB> {
+        let mut x = self.0.0;
+        x |= x << 8;
+        x |= x << 16;
+        x |= x << 32;
+        Bitboard(GenericBB(x))
+    }
+    // every square on or below a set square, on the same file
+    pub const fn south_fill(self) -> Bitboard<GenericBB> {
+        let mut x = self.0.0;
+        x |= x >> 8;
+        x |= x >> 16;
+        x |= x >> 32;
+        Bitboard(GenericBB(x))
+    }
+    // squares strictly in front of the set squares for the player, on their file and the
+    // adjacent ones: a pawn is passed when no enemy pawn stands in its front span
+    pub fn front_span(self, pl: Player) -> Bitboard<GenericBB> {
+        let front = match pl {
+            Player::White => self.lsu().north_fill(),
+            Player::Black => self.lsd().south_fill(),
+        };
+        front | front.lsl() | front.lsr()
+    }
+}
+
 impl<T: Display + BitboardSpec> Display for Bitboard<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
+impl Bitboard<GenericBB> {
+    // 8x8 grid for debugging, rank 8 on top as in Position::pretty_print
+    pub fn pretty(&self) -> String {
+        let mut s = String::new();
+        for rank in (0..8).rev() {
+            for file in 0..8 {
+                s.push(match (self.0.0 >> (8 * rank + file)) & 1 {
+                    1 => 'X',
+                    _ => '.',
+                });
+                s.push(' ');
+            }
+            s.push_str(&format!("{}\n", rank + 1));
+        }
+        s.push_str("a b c d e f g h");
+        s
+    }
+
+    // one flag per square, indexed by square index (a1 = 0, h1 = 7, h8 = 63)
+    pub fn to_array(&self) -> [bool; 64] {
+        std::array::from_fn(|i| (self.0.0 >> i) & 1 == 1)
+    }
+
+    pub fn from_array(squares: &[bool; 64]) -> Self {
+        let bits = (0..64).filter(|i| squares[*i]).fold(0, |x, i| x | 1 << i);
+        Bitboard(GenericBB(bits))
+    }
+}
+
 impl std::fmt::Debug for Bitboard<GenericBB> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Bb<Generic>({} ~ {})", self.0.0, self)
@@ -413,12 +505,12 @@ impl TryFrom<&str> for Bitboard<Square> {
         let file: Option<char> = chars.nth(0);
         let rank: Option<char> = chars.nth(0);
 
-        let inter = match file {
-            None | Some('-') => SpecialBB::Empty.declass(),
-            Some(f) => File::from_char(f).declass(),
-        } & match rank {
+        let inter = match file.and_then(File::from_char) {
             None => SpecialBB::Empty.declass(),
-            Some(f) => File::from_char(f).declass(),
+            Some(f) => f.declass(),
+        } & match rank.and_then(Rank::from_char) {
+            None => SpecialBB::Empty.declass(),
+            Some(r) => r.declass(),
         };
         // either a square or empty
         let x = Square::from_bb(&inter);
@@ -431,6 +523,27 @@ impl TryFrom<&str> for Bitboard<Square> {
     type Error = ();
 }
 
+impl TryFrom<(File, Rank)> for Bitboard<Square> {
+    fn try_from((file, rank): (File, Rank)) -> Result<Self, ()> {
+        Square::from_bb(&(file.declass() & rank)).ok_or(())
+    }
+
+    type Error = ();
+}
+
+// (file, rank) indices, both within 0..8
+impl TryFrom<(u8, u8)> for Bitboard<Square> {
+    fn try_from((file, rank): (u8, u8)) -> Result<Self, ()> {
+        if (file as usize) < File::COUNT && (rank as usize) < Rank::COUNT {
+            Ok(Self::from_index(rank * 8 + file))
+        } else {
+            Err(())
+        }
+    }
+
+    type Error = ();
+}
+
 impl Display for Square {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", *self)
@@ -449,7 +562,7 @@ impl<T: BitboardSpec> FromBB64<Square, T> for Square {
     }
 
     fn from_bb64(b: &T) -> Option<Self> {
-        if Bitboard(GenericBB(b.to_bb64())).into_iter().count() == 1 {
+        if b.count() == 1 {
             unsafe { Some(Self::from_bb64_nochecks(b)) }
         } else {
             None
@@ -525,35 +638,50 @@ impl Rank {
 }
 
 impl File {
-    const fn from_char(c: char) -> Self {
+    // 'a' to 'h', None otherwise
+    pub const fn from_char(c: char) -> Option<Self> {
         match c {
-            'a' => File::A,
-            'b' => File::B,
-            'c' => File::C,
-            'd' => File::D,
-            'e' => File::E,
-            'f' => File::F,
-            'g' => File::G,
-            'h' => File::H,
+            'a'..='h' => Some(Self::from_index(c as u8 - b'a')),
+            _ => None,
+        }
+    }
+    // 0 for A to 7 for H, panics past it
+    pub const fn from_index(x: u8) -> Self {
+        match x {
+            0 => File::A,
+            1 => File::B,
+            2 => File::C,
+            3 => File::D,
+            4 => File::E,
+            5 => File::F,
+            6 => File::G,
+            7 => File::H,
             _ => panic!(),
         }
     }
 }
 impl Rank {
-    /*
-    const fn from_char(c: char) -> Self {
+    // '1' to '8', None otherwise
+    pub const fn from_char(c: char) -> Option<Self> {
         match c {
-            '1' => Rank::R1,
-            '2' => Rank::R2,
-            '3' => Rank::R3,
-            '4' => Rank::R4,
-            '5' => Rank::R5,
-            '6' => Rank::R6,
-            '7' => Rank::R7,
-            '8' => Rank::R8,
+            '1'..='8' => Some(Self::from_index(c as u8 - b'1')),
+            _ => None,
+        }
+    }
+    // 0 for the first rank to 7 for the eighth, panics past it
+    pub const fn from_index(x: u8) -> Self {
+        match x {
+            0 => Rank::R1,
+            1 => Rank::R2,
+            2 => Rank::R3,
+            3 => Rank::R4,
+            4 => Rank::R5,
+            5 => Rank::R6,
+            6 => Rank::R7,
+            7 => Rank::R8,
             _ => panic!(),
         }
-    }*/
+    }
 }
 
 #[repr(u64)]
@@ -628,6 +756,17 @@ pub enum Square {
 impl Square {
     pub const COUNT: usize = 64;
 }
+// squares sort a1, b1, ..., h1, a2, ..., h8
+impl PartialOrd for Bitboard<Square> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Bitboard<Square> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.to_index().cmp(&other.to_index())
+    }
+}
 impl Bitboard<Square> {
     pub fn to_index(&self) -> u8 {
         (self.0 as u64).trailing_zeros() as u8
@@ -639,6 +778,12 @@ impl Bitboard<Square> {
     pub fn generic_from_index(x: u8) -> Bitboard<GenericBB> {
         Bitboard(GenericBB(1 << x))
     }
+    pub fn file(&self) -> File {
+        File::from_index(self.to_index() % 8)
+    }
+    pub fn rank(&self) -> Rank {
+        Rank::from_index(self.to_index() / 8)
+    }
 }
 impl From<Bitboard<PackedSquare>> for Bitboard<Square> {
     fn from(value: Bitboard<PackedSquare>) -> Self {
@@ -751,6 +896,166 @@ fn btype_tests() {
     assert_eq!(Bitboard(File::A) & Bitboard(Rank::R3), Bitboard(Square::a3));
 }
 
+#[test]
+fn square_from_coordinates() {
+    let e4: Bitboard<Square> = (File::E, Rank::R4).try_into().unwrap();
+    assert_eq!(e4, Bitboard(Square::e4));
+    let e4: Bitboard<Square> = (4, 3).try_into().unwrap();
+    assert_eq!(e4, Bitboard(Square::e4));
+    let h8: Bitboard<Square> = (7, 7).try_into().unwrap();
+    assert_eq!(h8, Bitboard(Square::h8));
+    assert!(Bitboard::<Square>::try_from((8, 0)).is_err());
+    assert!(Bitboard::<Square>::try_from((0, 8)).is_err());
+}
+
+#[test]
+fn square_file_and_rank() {
+    for x in 0..64 {
+        let sq = Bitboard::<Square>::from_index(x);
+        assert!(sq.file() == File::from_index(x % 8));
+        assert!(sq.rank() == Rank::from_index(x / 8));
+        assert_eq!(sq.file().declass() & sq.rank(), sq.declass());
+        // the name of the square, read back
+        let name = format!("{sq}");
+        let mut chars = name.chars();
+        assert!(File::from_char(chars.next().unwrap()) == Some(sq.file()));
+        assert!(Rank::from_char(chars.next().unwrap()) == Some(sq.rank()));
+    }
+    assert!(File::from_char('i').is_none());
+    assert!(Rank::from_char('0').is_none());
+    assert!(Bitboard::<Square>::try_from("z9").is_err());
+}
+
+#[test]
+fn hashable_bitboards() {
+    use std::collections::HashSet;
+    use std::hash::{BuildHasher, RandomState};
+
+    let s = RandomState::new();
+    let e4 = Bitboard(File::E) & Bitboard(Rank::R4);
+    assert_eq!(s.hash_one(e4), s.hash_one(Square::e4.declass()));
+
+    let mut masks = HashSet::new();
+    assert!(masks.insert(File::A.declass()));
+    assert!(masks.insert(e4.declass()));
+    assert!(!masks.insert(Bitboard(File::A).declass()));
+    assert!(masks.contains(&Square::e4.declass()));
+
+    let squares: HashSet<Bitboard<Square>> = Bitboard(File::A).into_iter().collect();
+    assert_eq!(squares.len(), 8);
+    assert!(squares.contains(&Bitboard(Square::a5)));
+}
+
+#[test]
+fn square_ordering() {
+    assert!(Bitboard(Square::a1) < Bitboard(Square::b1));
+    assert!(Bitboard(Square::b1) < Bitboard(Square::a2));
+    assert!(Bitboard(Square::h8) > Bitboard(Square::a8));
+
+    let mut squares: Vec<Bitboard<Square>> = Bitboard(Rank::R2).into_iter().collect();
+    squares.extend(Bitboard(File::A));
+    squares.sort();
+    squares.dedup();
+    assert_eq!(squares.first(), Some(&Bitboard(Square::a1)));
+    assert_eq!(
+        squares[1..9],
+        Bitboard(Rank::R2).into_iter().collect::<Vec<_>>()
+    );
+    assert_eq!(squares.last(), Some(&Bitboard(Square::a8)));
+}
+
+#[test]
+fn pretty_grid() {
+    let bb = Bitboard(Square::e4) | Bitboard(Square::a8) | Bitboard(Square::h1);
+    let expected = "\
+X . . . . . . . 8
+. . . . . . . . 7
+. . . . . . . . 6
+. . . . . . . . 5
+. . . . X . . . 4
+. . . . . . . . 3
+. . . . . . . . 2
+. . . . . . . X 1
+a b c d e f g h";
+    assert_eq!(bb.pretty(), expected);
+}
+
+#[test]
+fn array_conversion() {
+    let mut squares = [false; 64];
+    squares[0] = true;
+    assert!(Bitboard::from_array(&squares) == Square::a1.declass());
+    assert!(Square::h8.declass().to_array()[63]);
+    assert!(Square::h1.declass().to_array()[7]);
+
+    for bb in [
+        SpecialBB::Empty.declass(),
+        SpecialBB::Full.declass(),
+        Bitboard(Rank::R2).declass(),
+        Bitboard(File::C).declass(),
+        Bitboard(Square::e4) | Bitboard(Square::a8) | Bitboard(Square::h1),
+    ] {
+        let squares = bb.to_array();
+        assert_eq!(squares.iter().filter(|x| **x).count(), bb.count() as usize);
+        assert!(Bitboard::from_array(&squares) == bb);
+    }
+}
+
+#[test]
+fn count() {
+    assert_eq!(SpecialBB::Empty.declass().count(), 0);
+    assert_eq!(SpecialBB::Full.declass().count(), 64);
+    assert_eq!(Bitboard(Rank::R2).count(), 8);
+    let bb = Bitboard(Square::a1) | Bitboard(Square::h8) | Bitboard(Square::e4);
+    assert_eq!(bb.count() as usize, bb.into_iter().count());
+}
+
+#[test]
+fn vertical_shifts() {
+    let empty = SpecialBB::Empty.declass();
+    let a8 = Bitboard(Square::a8).declass();
+    assert_eq!(a8.shift_north(1), empty);
+    assert_eq!(a8 + 1, empty);
+    assert_eq!(Bitboard(Square::h1).declass().shift_south(1), empty);
+    assert_eq!(Rank::R8.declass().shift_south(7), Rank::R1.declass());
+    // no overflow however far the shift goes
+    assert_eq!(Rank::R1.declass().shift_north(8), empty);
+    assert_eq!(Rank::R8.declass().shift_south(100), empty);
+
+    // same as the operators, whatever the shift
+    let bb = Bitboard(Square::b2) | Bitboard(Square::e4) | Bitboard(Square::h7);
+    for n in 0..8 {
+        assert_eq!(bb.shift_north(n), bb + n as usize);
+        assert_eq!(bb.shift_south(n), bb - n as usize);
+    }
+    // horizontal shifts do not wrap to the next rank either
+    assert_eq!(Bitboard(Square::h4) >> 1, empty);
+    assert_eq!(Bitboard(Square::a4) << 1, empty);
+}
+
+#[test]
+fn pawn_fills() {
+    let e2 = Bitboard(Square::e2).declass();
+    assert_eq!(e2.north_fill(), File::E.declass() & !Rank::R1.declass());
+    assert_eq!(e2.south_fill(), Bitboard(Square::e1) | Bitboard(Square::e2));
+
+    let ahead = |f: File| f.declass() & !(Rank::R1.declass() | Rank::R2);
+    assert_eq!(
+        e2.front_span(Player::White),
+        ahead(File::D) | ahead(File::E) | ahead(File::F)
+    );
+    // no wrapping around the board edges
+    let a7 = Bitboard(Square::a7).declass();
+    assert_eq!(
+        a7.front_span(Player::Black),
+        (File::A.declass() | File::B) & !(Rank::R7.declass() | Rank::R8)
+    );
+    assert_eq!(
+        a7.front_span(Player::White),
+        Bitboard(Square::a8) | Bitboard(Square::b8)
+    );
+}
+
 #[cfg(test)]
 mod benchmarks {
     use super::*;
@@ -782,6 +1087,37 @@ mod benchmarks {
         });
     }
 
+    // popcount against iterating the squares, on boards of a few pieces up to half full
+    fn count_inputs() -> [Bitboard<GenericBB>; 4] {
+        [
+            0x10,
+            0x8100000000000081,
+            0xFFFF00000000FFFF,
+            0x55AA55AA55AA55AA,
+        ]
+        .map(|x| Bitboard(GenericBB(x)))
+    }
+
+    #[bench]
+    fn bench_count_popcount(b: &mut Bencher) {
+        let inputs = count_inputs();
+        b.iter(|| {
+            for bb in black_box(inputs) {
+                black_box(bb.count());
+            }
+        });
+    }
+
+    #[bench]
+    fn bench_count_iterator(b: &mut Bencher) {
+        let inputs = count_inputs();
+        b.iter(|| {
+            for bb in black_box(inputs) {
+                black_box(bb.into_iter().count());
+            }
+        });
+    }
+
     #[bench]
     fn bench_rank_file_intersection(b: &mut Bencher) {
         let rank = Rank::R4;
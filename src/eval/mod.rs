@@ -5,15 +5,22 @@
 //! - `ApproxEval` for centipawn-based evaluations with search depth
 //! - `ForcedMate` for forced mate sequences
 //! - `EvalState` for maintaining evaluation and principal variation
+//! - `MATE` anchored integer scores, mate distances counted from the search root
 //! - `MaterialBalance` trait for piece counting evaluations
+//! - `PawnStructureEval` adding a pawn structure term, cached in a pawn hash table
+//! - `PieceSquareEval` adding a bonus for the square of each piece
 //!
 //! The evaluation system supports both mate-in-N and centipawn scores,
 //! with proper comparison and nesting logic for search algorithms.
 mod s_count_material;
+mod s_pawn_structure;
+mod s_piece_square;
 
 use std::fmt::{Display, Formatter};
 
 pub use s_count_material::MaterialBalance;
+pub use s_pawn_structure::PawnStructureEval;
+pub use s_piece_square::PieceSquareEval;
 
 use super::prelude::*;
 
@@ -24,6 +31,9 @@ pub struct ApproxEval {
 }
 impl ApproxEval {
     pub const EQUAL: Self = ApproxEval { cp: 0, depth: 0 };
+    pub const fn new(cp: i32, depth: usize) -> Self {
+        ApproxEval { cp, depth }
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -32,17 +42,54 @@ pub struct ForcedMate {
     hmove_count: usize,
 }
 
+// forget what the evaluations cached, e.g. for a new game
+pub fn clear_caches() {
+    s_pawn_structure::clear_pawn_cache();
+}
+
 pub trait BasicEvaluation: Clone {
     fn t() -> Self;
     fn eval(p: &Position) -> Eval;
 }
 
+// EvalStyle: the BasicEvaluation a search uses, from material only to every positional term
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EvalStyle {
+    #[default]
+    Material,
+    Positional,
+    Psqt,
+}
+
+impl EvalStyle {
+    // option values, the default first
+    pub const NAMES: &[&str] = &["material", "positional", "psqt"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "material" => Some(Self::Material),
+            "positional" => Some(Self::Positional),
+            "psqt" => Some(Self::Psqt),
+            _ => None,
+        }
+    }
+
+    pub fn eval(self, p: &Position) -> Eval {
+        match self {
+            Self::Material => MaterialBalance::eval(p),
+            Self::Positional => PawnStructureEval::eval(p),
+            Self::Psqt => PieceSquareEval::eval(p),
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum Eval {
     Mate(ForcedMate),
     Approx(ApproxEval),
 }
 
+#[derive(Clone)]
 pub struct EvalState {
     pub eval: Eval,
     pub pv: MoveList,
@@ -50,12 +97,18 @@ pub struct EvalState {
 
 //// Internal defs/implementations
 
+#[derive(Clone)]
 pub struct MoveList(Vec<Move>);
 impl Default for MoveList {
     fn default() -> Self {
         MoveList { 0: Vec::new() }
     }
 }
+impl MoveList {
+    pub fn moves(&self) -> &[Move] {
+        &self.0
+    }
+}
 impl Display for MoveList {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "pv ")?;
@@ -147,13 +200,99 @@ impl Eval {
             _ => !Eval::pick_best_for(p, e1, e0),
         }
     }
+    // is e0 strictly better than e1 for p, search depth aside
+    pub fn better_for(p: Player, e0: Self, e1: Self) -> bool {
+        match (e0, e1) {
+            (Self::Approx(x), Self::Approx(y)) => match p {
+                Player::White => x.cp > y.cp,
+                Player::Black => x.cp < y.cp,
+            },
+            (Self::Mate(x), Self::Mate(y)) => {
+                (x.p != y.p || x.hmove_count != y.hmove_count)
+                    && ForcedMate::pick_best_for(p, &y, &x)
+            }
+            (Self::Mate(x), Self::Approx(_)) => x.p == p,
+            (Self::Approx(_), Self::Mate(y)) => y.p != p,
+        }
+    }
+    // same score at the same depth
+    fn is_same(self, other: Self) -> bool {
+        match (self, other) {
+            (Self::Approx(x), Self::Approx(y)) => x.cp == y.cp && x.depth == y.depth,
+            (Self::Mate(x), Self::Mate(y)) => x.p == y.p && x.hmove_count == y.hmove_count,
+            _ => false,
+        }
+    }
     fn nest(self) -> Self {
         match self {
             Self::Approx(x) => Self::Approx(x.nest()),
             Self::Mate(x) => Self::Mate(x.nest()),
         }
     }
+    // plies searched below the node, or to the mate
+    pub fn depth(self) -> usize {
+        match self {
+            Self::Approx(x) => x.depth,
+            Self::Mate(x) => x.hmove_count,
+        }
+    }
+    // inverse of nest: an eval of a node seen from one of its children
+    pub fn unnest(self) -> Self {
+        match self {
+            Self::Approx(x) => Self::Approx(ApproxEval {
+                cp: x.cp,
+                depth: x.depth.saturating_sub(1),
+            }),
+            Self::Mate(x) => Self::Mate(ForcedMate {
+                p: x.p,
+                hmove_count: x.hmove_count.saturating_sub(1),
+            }),
+        }
+    }
 }
+////// Ply anchored scores
+
+// bound of the integer scores, mate-in-N found at ply p scores MATE - (p + N)
+pub const MATE: i32 = 32000;
+// longest mate the integer scores can represent
+const MAX_MATE_PLY: i32 = 1024;
+
+pub fn is_mate_score(score: i32) -> bool {
+    score.abs() > MATE - MAX_MATE_PLY
+}
+
+impl Eval {
+    // integer score relative to the search root, self being the eval of a node at the given ply
+    pub fn score(self, ply: usize) -> i32 {
+        match self {
+            Self::Approx(x) => x.cp,
+            Self::Mate(x) => {
+                let s = MATE - (ply + x.hmove_count) as i32;
+                match x.p {
+                    Player::White => s,
+                    Player::Black => -s,
+                }
+            }
+        }
+    }
+    // eval of a node at the given ply, from a score relative to the search root
+    pub fn from_score(score: i32, ply: usize) -> Self {
+        match is_mate_score(score) {
+            true => Self::Mate(ForcedMate {
+                p: match score > 0 {
+                    true => Player::White,
+                    false => Player::Black,
+                },
+                hmove_count: ((MATE - score.abs()) as usize).saturating_sub(ply),
+            }),
+            false => Self::Approx(ApproxEval {
+                cp: score,
+                depth: 0,
+            }),
+        }
+    }
+}
+
 impl Display for Eval {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -164,7 +303,16 @@ impl Display for Eval {
 }
 
 impl EvalState {
+    // equal evaluations are resolved by the first move of the pv, smallest (from, to) squares
+    // first, so that the best move does not depend on the exploration order
     pub fn pick_best_for(p: Player, e0: Self, e1: Self) -> Self {
+        if e0.eval.is_same(e1.eval) {
+            let key = |e: &Self| e.pv.moves().first().map(Move::from_to);
+            return match key(&e1) < key(&e0) {
+                true => e1,
+                false => e0,
+            };
+        }
         match Eval::pick_best_for(p, e0.eval, e1.eval) {
             true => e1,
             _ => e0,
@@ -174,7 +322,8 @@ impl EvalState {
     // create the evalState for the current move, knowing that the eval is the best for player
     pub fn nest(&mut self, m: Move) {
         self.eval = self.eval.nest();
-        self.pv.0.push(m);
+        // pv is kept in playing order
+        self.pv.0.insert(0, m);
     }
     pub fn new(e: Eval) -> Self {
         Self {
@@ -191,3 +340,48 @@ impl Display for EvalState {
         write!(f, "{eval} {pv}",)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cloned_pv_is_independent() {
+        let moves = Position::startingpos().legal_moves();
+        let mut e = EvalState::new(Eval::draw());
+        e.nest(moves[0]);
+        let mut snapshot = e.clone();
+        snapshot.nest(moves[1]);
+        e.nest(moves[2]);
+        let (m0, m1, m2) = (moves[0], moves[1], moves[2]);
+        assert_eq!(format!("{}", e.pv), format!("pv {m2} {m0} "));
+        assert_eq!(format!("{}", snapshot.pv), format!("pv {m1} {m0} "));
+    }
+
+    #[test]
+    fn equal_evals_tie_break() {
+        let moves = Position::startingpos().legal_moves_for(Piece::Knight);
+        let line = |m: Move| {
+            let mut e = EvalState::new(Eval::draw());
+            e.nest(m);
+            e
+        };
+        // b1 is the lowest origin square, a3 the lowest destination from it
+        let expected = "b1a3";
+        for turn in [Player::White, Player::Black] {
+            let forward = moves
+                .iter()
+                .map(|m| line(*m))
+                .reduce(|a, b| EvalState::pick_best_for(turn, a, b))
+                .unwrap();
+            let backward = moves
+                .iter()
+                .rev()
+                .map(|m| line(*m))
+                .reduce(|a, b| EvalState::pick_best_for(turn, a, b))
+                .unwrap();
+            assert_eq!(format!("{}", forward.pv.moves()[0]), expected);
+            assert_eq!(format!("{}", backward.pv.moves()[0]), expected);
+        }
+    }
+}
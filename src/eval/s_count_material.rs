@@ -18,6 +18,9 @@ use super::BasicEvaluation;
 pub struct MaterialBalance {}
 impl BasicEvaluation for MaterialBalance {
     fn eval(p: &Position) -> Eval {
+        if p.is_fifty_move_draw() {
+            return Eval::draw();
+        }
         eval_fn(p)
     }
     fn t() -> Self {
@@ -26,8 +29,7 @@ impl BasicEvaluation for MaterialBalance {
 }
 
 impl Piece {
-    #[allow(unused)]
-    fn value(self) -> usize {
+    pub(crate) fn value(self) -> usize {
         match self {
             Piece::Pawn => 100,
             Piece::Knight => 300,
@@ -39,18 +41,15 @@ impl Piece {
     }
 }
 
-// TODO get rid of it when switching to parallel
-pub static mut NODES: usize = 0;
-
 fn eval_fn(p: &Position) -> Eval {
-    unsafe { NODES += 1 };
     use enum_iterator::all;
     let a = all::<Player>()
-        .zip(all::<Piece>())
+        .flat_map(|pl| all::<Piece>().map(move |pc| (pl, pc)))
+        .filter(|(_, pc)| *pc != Piece::King)
         .map(|(pl, pc)| -> isize {
             let ps = p.pos();
             let bb = ps[(pl, pc)];
-            (1 - 2 * (pl as isize)) * (bb.into_iter().count() as isize)
+            (1 - 2 * (pl as isize)) * (bb.count() as usize * pc.value()) as isize
         });
     let s: isize = a.sum();
     Eval::Approx(super::ApproxEval {
@@ -58,3 +57,33 @@ fn eval_fn(p: &Position) -> Eval {
         depth: 0,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cp(p: &Position) -> i32 {
+        match eval_fn(p) {
+            Eval::Approx(x) => x.cp,
+            Eval::Mate(_) => panic!("material balance never reports mates"),
+        }
+    }
+
+    #[test]
+    fn weighted_material() {
+        assert_eq!(cp(&Position::startingpos()), 0);
+        let p = Position::from_fen("3rk3/8/8/8/8/8/PP6/2BQK3", "w", "-", "-", "0", "1");
+        assert_eq!(cp(&p), 2 * 100 + 300 + 900 - 500);
+    }
+
+    #[test]
+    fn fifty_move_draw() {
+        let moves = ["a1a2", "e8d8", "a2a1", "d8e8"].repeat(25);
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/R3K3", "w", "-", "-", "0", "1");
+        let before = p.apply_uci_moves(&moves[..99]).unwrap();
+        assert!(matches!(MaterialBalance::eval(&before), Eval::Approx(x) if x.cp == 500));
+        let after = before.apply_uci_moves(&moves[99..]).unwrap();
+        assert!(after.is_fifty_move_draw());
+        assert!(matches!(MaterialBalance::eval(&after), Eval::Approx(x) if x.cp == 0));
+    }
+}
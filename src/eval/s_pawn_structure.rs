@@ -0,0 +1,163 @@
+//! Material balance corrected by the pawn structure
+//!
+//! For each side:
+//! - Doubled pawns = -20 for every extra pawn on a file
+//! - Isolated pawns = -15 (no friendly pawn on the adjacent files)
+//! - Passed pawns = +10 per rank advanced (no enemy pawn ahead on the same or adjacent files)
+//!
+//! The pawn term only depends on pawn placement, so it is cached in a pawn hash table.
+use std::{
+    cell::RefCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{ApproxEval, BasicEvaluation, Eval, MaterialBalance};
+use crate::prelude::*;
+
+const DOUBLED: i32 = 20;
+const ISOLATED: i32 = 15;
+const PASSED: i32 = 10;
+
+const PAWN_CACHE_SIZE: usize = 1 << 14;
+
+// each search thread has its own table, emptied when it lags behind this generation
+static PAWN_CACHE_GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static PAWN_CACHE: RefCell<(usize, PawnCache)> =
+        RefCell::new((0, PawnCache::new(PAWN_CACHE_SIZE)));
+}
+
+// empties the pawn table of every thread, on their next lookup
+pub fn clear_pawn_cache() {
+    PAWN_CACHE_GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Clone)]
+pub struct PawnStructureEval {}
+impl BasicEvaluation for PawnStructureEval {
+    fn eval(p: &Position) -> Eval {
+        if p.is_fifty_move_draw() {
+            return Eval::draw();
+        }
+        match MaterialBalance::eval(p) {
+            Eval::Approx(x) => Eval::Approx(ApproxEval {
+                cp: x.cp + cached_pawn_score(p),
+                depth: x.depth,
+            }),
+            mate => mate,
+        }
+    }
+    fn t() -> Self {
+        PawnStructureEval {}
+    }
+}
+
+fn cached_pawn_score(p: &Position) -> i32 {
+    let key = p.pawn_structure();
+    PAWN_CACHE.with(|c| {
+        let (generation, cache) = &mut *c.borrow_mut();
+        let current = PAWN_CACHE_GENERATION.load(Ordering::Relaxed);
+        if *generation != current {
+            *generation = current;
+            *cache = PawnCache::new(PAWN_CACHE_SIZE);
+        }
+        match cache[&key] {
+            Some(x) => x.cp,
+            None => {
+                let cp = pawn_score(p);
+                cache.push(&key, &PawnInfo { cp });
+                cp
+            }
+        }
+    })
+}
+
+const FILE_A: bb64 = File::A.bitboard();
+const FILE_H: bb64 = File::H.bitboard();
+
+fn pawn_score(p: &Position) -> i32 {
+    [Player::White, Player::Black]
+        .into_iter()
+        .map(|pl| {
+            let own = p.pos()[(pl, Piece::Pawn)];
+            let opp = p.pos()[(pl.other(), Piece::Pawn)];
+            (1 - 2 * (pl as i32)) * player_pawn_score(own, opp.to_bb64(), pl)
+        })
+        .sum()
+}
+
+fn adjacent_files(file: bb64) -> bb64 {
+    ((file << 1) & !FILE_A) | ((file >> 1) & !FILE_H)
+}
+
+fn player_pawn_score(own: Bitboard<GenericBB>, opp: bb64, pl: Player) -> i32 {
+    let mut score = 0;
+    let own_bb = own.to_bb64();
+    for f in 0..File::COUNT {
+        let file = FILE_A << f;
+        let count = (own_bb & file).count_ones() as i32;
+        if count > 1 {
+            score -= DOUBLED * (count - 1);
+        }
+        if own_bb & adjacent_files(file) == 0 {
+            score -= ISOLATED * count;
+        }
+    }
+    for sq in own {
+        let r = (sq.to_index() / 8) as i32;
+        if opp & sq.declass().front_span(pl).to_bb64() == 0 {
+            let advance = match pl {
+                Player::White => r - 1,
+                Player::Black => 6 - r,
+            };
+            score += PASSED * advance;
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pawn_terms() {
+        // doubled and isolated a pawns, both passed
+        let p = Position::from_fen("4k3/8/8/8/8/P7/P7/4K3", "w", "-", "-", "0", "1");
+        assert_eq!(pawn_score(&p), -DOUBLED - 2 * ISOLATED + PASSED);
+        // blocked and guarded pawns on both sides, mirrored
+        let p = Position::from_fen("4k3/3pp3/8/8/8/8/3PP3/4K3", "w", "-", "-", "0", "1");
+        assert_eq!(pawn_score(&p), 0);
+    }
+
+    #[test]
+    fn pawn_hash_shared_by_pawn_structure() {
+        let a = Position::from_fen("r3k3/ppp5/8/8/8/8/PPP5/R3K3", "w", "-", "-", "0", "1");
+        let b = Position::from_fen("4k2r/ppp5/8/3n4/8/8/PPP5/4KR2", "w", "-", "-", "0", "1");
+        let c = Position::from_fen("r3k3/pp6/2p5/8/8/8/PPP5/R3K3", "w", "-", "-", "0", "1");
+        assert_ne!(a.hash(), b.hash());
+        assert_eq!(a.pawn_key(), b.pawn_key());
+        assert_ne!(a.pawn_key(), c.pawn_key());
+
+        let mut cache = PawnCache::new(16);
+        cache.push(&a.pawn_structure(), &PawnInfo { cp: pawn_score(&a) });
+        assert_eq!(
+            cache[&b.pawn_structure()],
+            Some(PawnInfo { cp: pawn_score(&a) })
+        );
+        assert_eq!(cached_pawn_score(&b), pawn_score(&a));
+    }
+
+    #[test]
+    fn pawn_cache_cleared() {
+        let a = Position::from_fen("4k3/8/8/8/8/P7/P7/4K3", "w", "-", "-", "0", "1");
+        let cached = || PAWN_CACHE.with(|c| c.borrow().1[&a.pawn_structure()].is_some());
+        cached_pawn_score(&a);
+        assert!(cached());
+        clear_pawn_cache();
+        // emptied by the next lookup, whatever the position
+        cached_pawn_score(&Position::startingpos());
+        assert!(!cached());
+    }
+}
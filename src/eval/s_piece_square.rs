@@ -0,0 +1,170 @@
+//! Material balance corrected by piece-square tables
+//!
+//! Each piece gets a bonus (or a malus) depending on the square it stands on:
+//! - Pawns are pushed forward, the central ones first
+//! - Knights and bishops are kept away from the rim
+//! - Rooks like the seventh rank, the queen the center
+//! - The king stays sheltered behind its pawns
+//!
+//! Tables are written from White's point of view, rank 8 on top, and mirrored for Black.
+use super::{ApproxEval, BasicEvaluation, Eval, MaterialBalance};
+use crate::prelude::*;
+
+#[rustfmt::skip]
+const PAWN: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const KING: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+#[derive(Clone)]
+pub struct PieceSquareEval {}
+impl BasicEvaluation for PieceSquareEval {
+    fn eval(p: &Position) -> Eval {
+        if p.is_fifty_move_draw() {
+            return Eval::draw();
+        }
+        match MaterialBalance::eval(p) {
+            Eval::Approx(x) => Eval::Approx(ApproxEval {
+                cp: x.cp + square_score(p),
+                depth: x.depth,
+            }),
+            mate => mate,
+        }
+    }
+    fn t() -> Self {
+        PieceSquareEval {}
+    }
+}
+
+const fn table(piece: Piece) -> &'static [i32; 64] {
+    match piece {
+        Piece::Pawn => &PAWN,
+        Piece::Knight => &KNIGHT,
+        Piece::Bishop => &BISHOP,
+        Piece::Rook => &ROOK,
+        Piece::Queen => &QUEEN,
+        Piece::King => &KING,
+    }
+}
+
+// bonus of a piece of pl on sq, the tables starting with rank 8
+fn bonus(pl: Player, piece: Piece, sq: Bitboard<Square>) -> i32 {
+    let index = match pl {
+        Player::White => sq.to_index() ^ 56,
+        Player::Black => sq.to_index(),
+    };
+    table(piece)[index as usize]
+}
+
+fn square_score(p: &Position) -> i32 {
+    use enum_iterator::all;
+    all::<Player>()
+        .flat_map(|pl| all::<Piece>().map(move |pc| (pl, pc)))
+        .map(|(pl, pc)| {
+            let sum: i32 = p.pos()[(pl, pc)]
+                .into_iter()
+                .map(|sq| bonus(pl, pc, sq))
+                .sum();
+            (1 - 2 * (pl as i32)) * sum
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored_tables() {
+        assert_eq!(square_score(&Position::startingpos()), 0);
+        for piece in enum_iterator::all::<Piece>() {
+            for x in 0..64 {
+                let sq = Bitboard::from_index(x);
+                let mirror = Bitboard::from_index(x ^ 56);
+                assert_eq!(
+                    bonus(Player::White, piece, sq),
+                    bonus(Player::Black, piece, mirror)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn centralized_knight() {
+        // after 1. e4 e5, the king's knight on f3 or on h3
+        let center = Position::from_startpos_moves(&["e2e4", "e7e5", "g1f3"]).unwrap();
+        let rim = Position::from_startpos_moves(&["e2e4", "e7e5", "g1h3"]).unwrap();
+        assert!(PieceSquareEval::eval(&center).score(0) > PieceSquareEval::eval(&rim).score(0));
+        // same material, the material balance cannot tell them apart
+        assert_eq!(
+            MaterialBalance::eval(&center).score(0),
+            MaterialBalance::eval(&rim).score(0)
+        );
+    }
+}
@@ -5,19 +5,29 @@
 #![feature(impl_trait_in_assoc_type)]
 #![feature(associated_type_defaults)]
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "silent")))]
 use std::io::Write;
 use std::time::SystemTime;
 use std::{io::Stdout, sync::LazyLock};
 
 use uci::{UciOut, UciShell};
+
+// println!, compiled out by the silent feature
+macro_rules! dprintln {
+    ($($arg:tt)*) => {
+        if cfg!(not(feature = "silent")) {
+            println!($($arg)*);
+        }
+    };
+}
+
 pub mod algorithms;
 pub mod prelude;
 use prelude::*;
 pub mod bitboard;
 pub mod eval;
-#[deprecated]
 pub mod localvec;
+pub mod pgn;
 pub mod piece;
 pub mod player;
 pub mod position;
@@ -31,7 +41,7 @@ extern crate enum_iterator;
 
 static START_TIME: LazyLock<SystemTime> = LazyLock::new(|| SystemTime::now());
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "silent")))]
 fn loglevel() -> log::LevelFilter {
     let x = std::env::var("LOG");
     match x {
@@ -65,7 +75,7 @@ fn loglevel() -> log::LevelFilter {
     }
 }
 
-#[cfg(debug_assertions)]
+#[cfg(all(debug_assertions, not(feature = "silent")))]
 fn setup_logger() {
     env_logger::Builder::new()
         .filter_level(loglevel())
@@ -86,11 +96,55 @@ fn setup_logger() {
         .init();
 }
 
-#[cfg(not(debug_assertions))]
+#[cfg(all(not(debug_assertions), not(feature = "silent")))]
 fn setup_logger() {
     ();
 }
 
+// no logger installed, the log macros end up in log's no-op one
+#[cfg(feature = "silent")]
+fn setup_logger() {
+    log::set_max_level(log::LevelFilter::Off);
+}
+
+#[cfg(all(test, feature = "silent"))]
+mod tests {
+    use crate::{
+        eval::MaterialBalance,
+        prelude::*,
+        search::{Search, SearchDefault},
+    };
+
+    // set in the child process running the search
+    const CHILD: &str = "RUCHES_SILENT_SEARCH";
+
+    // the search runs in a child process of the test binary, its stdout not captured by the
+    // harness: nothing may be printed between the two markers
+    #[test]
+    fn silent_search() {
+        if std::env::var_os(CHILD).is_some() {
+            super::setup_logger();
+            assert!(!log::log_enabled!(log::Level::Error));
+            println!("<<");
+            let e = SearchDefault::fixed_depth::<MaterialBalance>(Position::startingpos(), 3);
+            log::error!("not shown");
+            #[cfg(debug_assertions)]
+            crate::tt::PerftCache::new(16).print_stats();
+            println!(">>");
+            assert_eq!(e.pv.moves().len(), 3);
+            return;
+        }
+        let child = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["tests::silent_search", "--exact", "--nocapture"])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&child.stdout);
+        assert!(child.status.success(), "{stdout}");
+        assert!(stdout.contains("<<\n>>\n"), "{stdout}");
+    }
+}
+
 #[tokio::main/*(flavor = "current_thread")*/]
 async fn main() {
     let _ = START_TIME.clone();
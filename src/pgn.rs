@@ -0,0 +1,158 @@
+//! Minimal PGN import
+//!
+//! Replays the movetext of a single game, from the starting position or from the one given by
+//! its `[FEN "..."]` tag. Comments (`{...}` and `;` to the end of the line), NAGs (`$n`),
+//! variations `(...)` and move numbers are skipped, a result token ends the game.
+use std::fmt::Display;
+
+use crate::prelude::*;
+
+/// Error raised when a PGN cannot be replayed
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    // the FEN tag does not hold a position
+    BadFen(String),
+    // a comment, a variation or a tag left open
+    Unterminated(char),
+    // a closing character with nothing open to close
+    Unbalanced(char),
+    // the move at this index of the game is malformed, illegal or ambiguous
+    BadMove { index: usize, san: String },
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::BadFen(fen) => write!(f, "bad FEN tag {fen}"),
+            PgnError::Unterminated(c) => write!(f, "unterminated {c}"),
+            PgnError::Unbalanced(c) => write!(f, "unbalanced {c}"),
+            PgnError::BadMove { index, san } => write!(f, "bad move {san} (move {index})"),
+        }
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+// moves of the game and the position they lead to
+pub fn import(pgn: &str) -> Result<(Vec<Move>, Position), PgnError> {
+    let (movetext, fen) = strip(pgn)?;
+    let mut pos = match fen {
+        Some(fen) => {
+            Position::extract_fen(&mut fen.split_whitespace()).ok_or(PgnError::BadFen(fen))?
+        }
+        None => Position::startingpos(),
+    };
+    let mut moves = Vec::new();
+    for word in movetext.split_whitespace() {
+        if RESULTS.contains(&word) {
+            break;
+        }
+        // castles are often written with zeros
+        let word = match word.starts_with("0-0") {
+            true => word.replace('0', "O"),
+            false => word.to_string(),
+        };
+        // "12.", "12...", or glued to the move as in "12.Nf3"
+        let san = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if san.is_empty() || san.starts_with('$') {
+            continue;
+        }
+        let san = san.to_string();
+        let bad_move = || PgnError::BadMove {
+            index: moves.len(),
+            san: san.clone(),
+        };
+        let m = pos
+            .getmove_san(&san)
+            .map_err(|_| bad_move())?
+            .ok_or_else(bad_move)?;
+        pos.stack(&m, m.promotion());
+        moves.push(m);
+    }
+    Ok((moves, pos))
+}
+
+// movetext without tags, comments nor variations, and the value of the FEN tag if any
+fn strip(pgn: &str) -> Result<(String, Option<String>), PgnError> {
+    let mut movetext = String::new();
+    let mut fen = None;
+    let mut chars = pgn.chars();
+    let mut variations = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                chars
+                    .find(|c| *c == '}')
+                    .ok_or(PgnError::Unterminated('{'))?;
+            }
+            ';' => {
+                chars.find(|c| *c == '\n');
+            }
+            '(' => variations += 1,
+            ')' if variations == 0 => return Err(PgnError::Unbalanced(')')),
+            ')' => variations -= 1,
+            '[' if variations == 0 => {
+                let tag: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                if let Some(("FEN", value)) = tag.trim().split_once(char::is_whitespace) {
+                    fen = Some(value.trim().trim_matches('"').to_string());
+                }
+            }
+            c if variations == 0 => {
+                movetext.push(c);
+                continue;
+            }
+            _ => continue,
+        }
+        // words on both sides of a skipped part stay apart
+        movetext.push(' ');
+    }
+    match variations {
+        0 => Ok((movetext, fen)),
+        _ => Err(PgnError::Unterminated('(')),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn annotated_game() {
+        let pgn = r#"[Event "Casual game"]
+[White "A"]
+[Black "B"]
+
+1. e4 {best by test} e5 2. Nf3 $1 Nc6 (2... d6 3. d4 {Philidor} (3. Bc4)) 3.Bb5 a6!?
+4. Bxc6 dxc6 ; exchange variation
+5. 0-0 f6 1-0 6. d4"#;
+        let (moves, pos) = import(pgn).unwrap();
+        assert_eq!(moves.len(), 10);
+        assert_eq!(
+            pos.to_fen(),
+            "r1bqkbnr/1pp3pp/p1p2p2/4p3/4P3/5N2/PPPP1PPP/RNBQ1RK1 w kq - 0 6"
+        );
+        let uci: Vec<String> = moves.iter().map(|m| format!("{m}")).collect();
+        assert!(pos == Position::startingpos().apply_uci_moves(&uci).unwrap());
+    }
+
+    #[test]
+    fn fen_tag() {
+        let pgn = "[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]\n1. e4 Kd7 *";
+        let (_, pos) = import(pgn).unwrap();
+        assert_eq!(pos.to_fen(), "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2");
+    }
+
+    #[test]
+    fn bad_games() {
+        let error = |pgn| import(pgn).unwrap_err();
+        let bad_move = PgnError::BadMove {
+            index: 2,
+            san: "Ke3".to_string(),
+        };
+        assert_eq!(error("1. e4 e5 2. Ke3"), bad_move);
+        assert_eq!(error("1. e4 {open"), PgnError::Unterminated('{'));
+        assert_eq!(error("1. e4 (1. d4"), PgnError::Unterminated('('));
+        assert_eq!(error("1. e4 1. d4) e5"), PgnError::Unbalanced(')'));
+        assert!(matches!(error("[FEN \"x\"] 1. e4"), PgnError::BadFen(_)));
+    }
+}
@@ -24,15 +24,28 @@ impl Piece {
         match value {
             0 => Some(Self::Pawn),
             1 => Some(Self::Knight),
-            2 => Some(Self::King),
-            3 => Some(Self::Bishop),
-            4 => Some(Self::Rook),
-            5 => Some(Self::Queen),
+            2 => Some(Self::Bishop),
+            3 => Some(Self::Rook),
+            4 => Some(Self::Queen),
+            5 => Some(Self::King),
             _ => None,
         }
     }
 }
 
+impl Piece {
+    // bishops, rooks and queens move along rays that can be blocked
+    pub const fn is_slider(self) -> bool {
+        matches!(self, Piece::Bishop | Piece::Rook | Piece::Queen)
+    }
+    pub const fn is_minor(self) -> bool {
+        matches!(self, Piece::Knight | Piece::Bishop)
+    }
+    pub const fn is_major(self) -> bool {
+        matches!(self, Piece::Rook | Piece::Queen)
+    }
+}
+
 impl Piece {
     // return ranks of start positions (combined in a bitboard)
     fn starting_files(self) -> Bitboard<GenericBB> {
@@ -74,3 +87,35 @@ impl Piece {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classification() {
+        use enum_iterator::all;
+        let class = |p: Piece| (p.is_slider(), p.is_minor(), p.is_major());
+        let expected = [
+            (Piece::Pawn, (false, false, false)),
+            (Piece::Knight, (false, true, false)),
+            (Piece::Bishop, (true, true, false)),
+            (Piece::Rook, (true, false, true)),
+            (Piece::Queen, (true, false, true)),
+            (Piece::King, (false, false, false)),
+        ];
+        assert_eq!(all::<Piece>().count(), expected.len());
+        for (p, c) in expected {
+            assert_eq!(class(p), c, "{p:?}");
+        }
+    }
+
+    #[test]
+    fn usize_round_trip() {
+        use enum_iterator::all;
+        for p in all::<Piece>() {
+            assert_eq!(Piece::from_usize(p as usize), Some(p));
+        }
+        assert_eq!(Piece::from_usize(Piece::COUNT), None);
+    }
+}
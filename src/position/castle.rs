@@ -16,30 +16,67 @@ pub enum Castle {
 }
 impl Castle {
     const COUNT: usize = 2;
-    pub const fn king_dest_file(&self) -> Bitboard<File> {
+    // files are numbered from 0 (A) to 7 (H)
+    // where the king and the rook land, in standard chess as in Chess960
+    pub const fn king_dest_file(&self) -> u8 {
         match self {
-            Self::Long => Bitboard(File::C),
-            Self::Short => Bitboard(File::G),
+            Self::Long => 2,
+            Self::Short => 6,
         }
     }
-    pub const fn files(&self) -> Bitboard<GenericBB> {
+    pub const fn rook_dest_file(&self) -> u8 {
         match self {
-            Self::Long => CASTLE_FILES_LONG,
-            Self::Short => CASTLE_FILES_SHORT,
+            Self::Long => 3,
+            Self::Short => 5,
         }
     }
-    pub const fn free_files(&self) -> Bitboard<GenericBB> {
+    // file of the castling rook in standard chess
+    pub const fn rook_file(&self) -> u8 {
         match self {
-            Self::Long => CASTLE_FILES_LONG_FREE,
-            Self::Short => CASTLE_FILES_SHORT_FREE,
+            Self::Short => 7,
+            Self::Long => 0,
         }
     }
-    pub const fn rook_file(&self) -> Bitboard<File> {
-        match self {
-            Self::Short => Bitboard(File::H),
-            Self::Long => Bitboard(File::A),
+}
+
+// files of the king and of the rook taking part in a castle, before it is played
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CastleFiles {
+    pub king: u8,
+    pub rook: u8,
+}
+
+impl CastleFiles {
+    pub const fn standard(c: Castle) -> Self {
+        CastleFiles {
+            king: 4,
+            rook: c.rook_file(),
         }
     }
+    // king on the E file and rook in the corner, as in standard chess
+    pub fn is_standard(&self, c: Castle) -> bool {
+        *self == Self::standard(c)
+    }
+    // squares that have to be empty, apart from the king and the rook themselves
+    pub fn path(&self, c: Castle, p: Player) -> Bitboard<GenericBB> {
+        let crossed = span(self.king, c.king_dest_file()) | span(self.rook, c.rook_dest_file());
+        crossed & !(file(self.king) | file(self.rook)) & p.backrank()
+    }
+    // squares the king starts from, crosses and lands on, none of them may be attacked
+    pub fn king_path(&self, c: Castle, p: Player) -> Bitboard<GenericBB> {
+        span(self.king, c.king_dest_file()) & p.backrank()
+    }
+}
+
+fn file(f: u8) -> Bitboard<GenericBB> {
+    File::A.declass() >> f as usize
+}
+
+// files from a to b included
+fn span(a: u8, b: u8) -> Bitboard<GenericBB> {
+    (a.min(b)..=a.max(b))
+        .map(file)
+        .fold(SpecialBB::Empty.declass(), |x, f| x | f)
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -66,8 +103,12 @@ impl Index<Castle> for CastleRights {
 pub struct CastleData {
     // x: [CastleRights; Player::COUNT], // former representation, not memory efficient
     x: u8,
+    // file of the rook of each castle, indexed as the rights: A and H unless in Chess960
+    rooks: [u8; Player::COUNT * Castle::COUNT],
 }
 
+const STANDARD_ROOKS: [u8; Player::COUNT * Castle::COUNT] = [7, 0, 7, 0];
+
 impl CastleData {
     pub fn stack_rev(&mut self, other: &CastleData) {
         /*for (index, value) in other.x.iter().enumerate() {
@@ -75,6 +116,30 @@ impl CastleData {
         }*/
         self.x ^= other.x
     }
+    pub fn rook_file(&self, p: Player, c: Castle) -> u8 {
+        self.rooks[Castle::COUNT * (p as usize) + c as usize]
+    }
+    pub fn set_rook_file(&mut self, p: Player, c: Castle, file: u8) {
+        self.rooks[Castle::COUNT * (p as usize) + c as usize] = file;
+    }
+    // king and rook files of the castle, the king standing on king
+    pub fn files(&self, p: Player, c: Castle, king: Bitboard<Square>) -> CastleFiles {
+        CastleFiles {
+            king: king.to_index() % 8,
+            rook: self.rook_file(p, c),
+        }
+    }
+    // a piece of p left sq, or was captured there: castling with a rook from sq is lost
+    pub fn remove_rook(&mut self, p: Player, sq: Bitboard<Square>) {
+        for c in [Castle::Short, Castle::Long] {
+            if sq.declass() & p.backrank() != SpecialBB::Empty.declass()
+                && sq.to_index() % 8 == self.rook_file(p, c)
+            {
+                self.set(p, c, false);
+            }
+        }
+    }
+
     pub fn fetch(&self, p: Player, c: Castle) -> bool {
         let mask: u8 = 1 << (Castle::COUNT * (p as usize) + c as usize);
         self.x & mask != 0
@@ -98,6 +163,57 @@ impl CastleData {
         self.x = (self.x & !mask) | (mask & val.x);
     }
 
+    // castling field of a FEN, either standard (KQkq) or X-FEN/Shredder-FEN rook files (HAha)
+    pub fn from_xfen(s: &str, pos: &Position) -> Option<CastleData> {
+        let mut castles = CASTLES_ALL_FORBIDDEN;
+        if s == "-" {
+            return Some(castles);
+        }
+        for c in s.chars() {
+            let (pl, side, file) = Self::xfen_right(c, pos)?;
+            castles.set(pl, side, true);
+            castles.set_rook_file(pl, side, file);
+        }
+        Some(castles)
+    }
+
+    // right given by one letter of the castling field and the file of its rook, None if it is
+    // not one; a rook file is a short castle when it lies on the king side of its king
+    // K and Q stand for the outermost rook on their side, the corner one if there is none
+    pub fn xfen_right(c: char, pos: &Position) -> Option<(Player, Castle, u8)> {
+        let pl = match c.is_ascii_uppercase() {
+            true => Player::White,
+            false => Player::Black,
+        };
+        let king = pos.pos[(pl, Piece::King)].into_iter().next();
+        let king_file = king.map(|k| k.to_index() % 8);
+        let rooks = pos.pos[(pl, Piece::Rook)] & pl.backrank();
+        let rook_files = rooks.into_iter().map(|r| r.to_index() % 8);
+        match c.to_ascii_lowercase() {
+            'k' => {
+                let outer = rook_files
+                    .filter(|f| king_file.is_some_and(|k| *f > k))
+                    .max();
+                Some((pl, Castle::Short, outer.unwrap_or(7)))
+            }
+            'q' => {
+                let outer = rook_files
+                    .filter(|f| king_file.is_some_and(|k| *f < k))
+                    .min();
+                Some((pl, Castle::Long, outer.unwrap_or(0)))
+            }
+            f @ 'a'..='h' => {
+                let file = f as u8 - b'a';
+                match file.cmp(&king_file?) {
+                    std::cmp::Ordering::Greater => Some((pl, Castle::Short, file)),
+                    std::cmp::Ordering::Less => Some((pl, Castle::Long, file)),
+                    std::cmp::Ordering::Equal => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
     pub fn hash(&self) -> usize {
         // TODO: improve speed
         /*let mut h = 0;
@@ -114,20 +230,96 @@ impl CastleData {
     }
 }
 
-pub const CASTLES_ALL_ALLOWED: CastleData = CastleData { x: 0xF };
-pub const CASTLES_ALL_FORBIDDEN: CastleData = CastleData { x: 0x0 };
+// castling field of a FEN, "-" when no castle is allowed
+impl std::fmt::Display for CastleData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut any = false;
+        for (pl, c, ch) in [
+            (Player::White, Castle::Short, 'K'),
+            (Player::White, Castle::Long, 'Q'),
+            (Player::Black, Castle::Short, 'k'),
+            (Player::Black, Castle::Long, 'q'),
+        ] {
+            if self.fetch(pl, c) {
+                // Shredder-FEN file letter for a rook that does not start in the corner
+                match self.rook_file(pl, c) == c.rook_file() {
+                    true => write!(f, "{ch}")?,
+                    false => {
+                        let file = (b'a' + self.rook_file(pl, c)) as char;
+                        match pl {
+                            Player::White => write!(f, "{}", file.to_ascii_uppercase())?,
+                            Player::Black => write!(f, "{file}")?,
+                        }
+                    }
+                }
+                any = true;
+            }
+        }
+        match any {
+            true => Ok(()),
+            false => write!(f, "-"),
+        }
+    }
+}
+
+pub const CASTLES_ALL_ALLOWED: CastleData = CastleData {
+    x: 0xF,
+    rooks: STANDARD_ROOKS,
+};
+pub const CASTLES_ALL_FORBIDDEN: CastleData = CastleData {
+    x: 0x0,
+    rooks: STANDARD_ROOKS,
+};
 
 pub const CASTLES_KEEP_UNCHANGED: CastleData = CASTLES_ALL_FORBIDDEN;
 
-pub const CASTLE_FILES_SHORT: Bitboard<GenericBB> = Bitboard(GenericBB(
-    File::E.bitboard() | File::F.bitboard() | File::G.bitboard(),
-));
-pub const CASTLE_FILES_LONG: Bitboard<GenericBB> = Bitboard(GenericBB(
-    File::C.bitboard() | File::D.bitboard() | File::E.bitboard(),
-));
-
-pub const CASTLE_FILES_SHORT_FREE: Bitboard<GenericBB> =
-    Bitboard(GenericBB(File::F.bitboard() | File::G.bitboard()));
-pub const CASTLE_FILES_LONG_FREE: Bitboard<GenericBB> = Bitboard(GenericBB(
-    File::B.bitboard() | File::C.bitboard() | File::D.bitboard(),
-));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xfen_castling_field() {
+        let p = Position::startingpos();
+        let standard = CastleData::from_xfen("KQkq", &p);
+        assert_eq!(standard, Some(CASTLES_ALL_ALLOWED));
+        assert_eq!(CastleData::from_xfen("HAha", &p), standard);
+        assert_eq!(CastleData::from_xfen("-", &p), Some(CASTLES_ALL_FORBIDDEN));
+
+        let mixed = CastleData::from_xfen("Kq", &p).unwrap();
+        assert_eq!(CastleData::from_xfen("Ha", &p), Some(mixed));
+        assert!(mixed.fetch(Player::White, Castle::Short));
+        assert!(!mixed.fetch(Player::White, Castle::Long));
+        assert!(!mixed.fetch(Player::Black, Castle::Short));
+        assert!(mixed.fetch(Player::Black, Castle::Long));
+
+        // king file and unknown letters
+        assert_eq!(CastleData::from_xfen("E", &p), None);
+        assert_eq!(CastleData::from_xfen("Kx", &p), None);
+
+        // displayed back as in a standard FEN
+        assert_eq!(format!("{mixed}"), "Kq");
+        assert_eq!(format!("{}", standard.unwrap()), "KQkq");
+        assert_eq!(format!("{CASTLES_ALL_FORBIDDEN}"), "-");
+
+        // X-FEN is also accepted by the fen parser
+        let fen = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R", "w", "HAha", "-", "0", "1");
+        let std = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R", "w", "KQkq", "-", "0", "1");
+        assert!(fen == std);
+    }
+
+    #[test]
+    fn shredder_fen_does_not_panic() {
+        // a Chess960 start keeps its rights, written back with the rook files
+        let fen = "bqnbrkrn/pppppppp/8/8/8/8/PPPPPPPP/BQNBRKRN";
+        let p = Position::from_fen(fen, "w", "GEge", "-", "0", "1");
+        assert_eq!(p.to_fen(), format!("{fen} w GEge - 0 1"));
+        assert!(p.is_chess960());
+        assert!(!Position::startingpos().is_chess960());
+        // unknown letters are ignored, the valid ones kept
+        let p = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R", "w", "KxE?q", "-", "0", "1");
+        assert_eq!(format!("{}", p.castles), "Kq");
+        // no black king to tell the side of the black rook files
+        let p = Position::from_fen("8/8/8/8/8/8/8/R3K2R", "w", "HAh", "-", "0", "1");
+        assert_eq!(format!("{}", p.castles), "KQ");
+    }
+}
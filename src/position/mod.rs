@@ -3,17 +3,20 @@ pub trait Parsing {
     fn from_str(s: &str) -> Self::Resulting;
 }
 
-use castle::{CASTLES_ALL_ALLOWED, CASTLES_ALL_FORBIDDEN, Castle, CastleData};
+use castle::{CASTLES_ALL_ALLOWED, CASTLES_ALL_FORBIDDEN, Castle, CastleData, CastleFiles};
 
 pub use movegen::{AtomicMove, AugmentedPos, Change, Move, Promotion, StandardMove};
+pub use zobrist::PawnStructure;
 
 pub mod types;
 pub use types::*;
 mod castle;
 pub mod movegen;
+mod see;
 mod zobrist;
 use crate::prelude::*;
 use crate::uci::UciOutputStream;
+use std::fmt::Display;
 
 pub trait PositionSpec: Sized {
     fn startingpos() -> Self;
@@ -29,12 +32,36 @@ pub trait PositionSpec: Sized {
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Position {
     pub fifty_mv: u16,
-    half_move_count: u16,
+    half_move_count: u32,
     pos: PlayerStorage,
     castles: CastleData,
     en_passant: Bitboard<GenericBB>,
 }
 
+// state lost when stacking a move, needed to unstack it
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Unstack {
+    captured: Option<(Piece, Bitboard<Square>)>,
+    promoted: Option<Piece>,
+    castles: CastleData,
+    en_passant: Bitboard<GenericBB>,
+    fifty_mv: u16,
+}
+
+// (piece, src, dest) of the king and rook movements of a castle
+fn castle_squares(
+    c: Castle,
+    p: Player,
+    files: CastleFiles,
+) -> [(Piece, Bitboard<Square>, Bitboard<Square>); 2] {
+    let rank = 56 * p as u8;
+    let square = |file| Bitboard::from_index(rank + file);
+    [
+        (Piece::King, square(files.king), square(c.king_dest_file())),
+        (Piece::Rook, square(files.rook), square(c.rook_dest_file())),
+    ]
+}
+
 impl PositionSpec for Position {
     fn startingpos() -> Position {
         Position {
@@ -78,9 +105,9 @@ impl Position {
         task: impl Fn(&Self, &Move) -> R,
         reduce: impl Fn(R, R) -> R,
     ) -> Option<R> {
+        log::trace!("listing outcomes for {}", ch.describe(&self));
         match ch {
             Move::Normal(ch) => {
-                log::trace!("listing outcomes for {}-{}", ch.src, ch.dest);
                 let fmv = self.fifty_mv;
                 self.fifty_mv += 1;
 
@@ -137,16 +164,7 @@ impl Position {
 
                 if ch.piece == Piece::Rook {
                     // moving rook away
-                    if (ch.src.declass() & (turn.backrank() & File::A.bb()))
-                        != SpecialBB::Empty.declass()
-                    {
-                        self.castles.set(turn, Castle::Long, false);
-                    }
-                    if (ch.src.declass() & (turn.backrank() & File::H.bb()))
-                        != SpecialBB::Empty.declass()
-                    {
-                        self.castles.set(turn, Castle::Short, false);
-                    }
+                    self.castles.remove_rook(turn, ch.src.into());
                 }
                 if ch.piece == Piece::King {
                     // moving king away
@@ -158,16 +176,7 @@ impl Position {
                 }
 
                 // capturing other player's rook
-                if (ch.dest.declass() & (turn.other().backrank() & File::A.bb()))
-                    != SpecialBB::Empty.declass()
-                {
-                    self.castles.set(turn.other(), Castle::Long, false);
-                }
-                if (ch.dest.declass() & (turn.other().backrank() & File::H.bb()))
-                    != SpecialBB::Empty.declass()
-                {
-                    self.castles.set(turn.other(), Castle::Short, false);
-                }
+                self.castles.remove_rook(turn.other(), ch.dest.into());
 
                 //// preparations done, now inspecting
 
@@ -185,7 +194,7 @@ impl Position {
                         self.pos.remove_piece(turn, Piece::Pawn, ch.dest.into());
                         let peek = |&p| -> R {
                             self.pos.add_new_piece(turn, p, ch.dest.into());
-                            let r = task(&self, &Move::Normal(*ch));
+                            let r = task(&self, &Move::Normal(*ch).with_promotion(Some(p)));
                             self.pos.remove_piece(turn, p, ch.dest.into());
                             r
                         };
@@ -212,7 +221,7 @@ impl Position {
                     .move_piece(turn, ch.piece, ch.dest.into(), ch.src.into());
                 self.castles = cda_old;
                 self.en_passant ^= en_passant_change;
-                self.half_move_count -= 1; // TODO: fifty mv rule
+                self.half_move_count -= 1;
                 self.fifty_mv = fmv;
 
                 // Clean state
@@ -232,34 +241,8 @@ impl Position {
 
                 res
             }
-            Move::Castle(c, p) => {
-                // Castle moves are filtered before, no need to check legality
-                match (c, p) {
-                    (Castle::Short, Player::White) => {
-                        self.pos
-                            .move_piece(*p, Piece::King, Square::e1.bb(), Square::g1.bb());
-                        self.pos
-                            .move_piece(*p, Piece::Rook, Square::h1.bb(), Square::f1.bb());
-                    }
-                    (Castle::Long, Player::White) => {
-                        self.pos
-                            .move_piece(*p, Piece::King, Square::e1.bb(), Square::c1.bb());
-                        self.pos
-                            .move_piece(*p, Piece::Rook, Square::a1.bb(), Square::d1.bb());
-                    }
-                    (Castle::Short, Player::Black) => {
-                        self.pos
-                            .move_piece(*p, Piece::King, Square::e8.bb(), Square::g8.bb());
-                        self.pos
-                            .move_piece(*p, Piece::Rook, Square::h8.bb(), Square::f8.bb());
-                    }
-                    (Castle::Long, Player::Black) => {
-                        self.pos
-                            .move_piece(*p, Piece::King, Square::e8.bb(), Square::c8.bb());
-                        self.pos
-                            .move_piece(*p, Piece::Rook, Square::a8.bb(), Square::d8.bb());
-                    }
-                };
+            Move::Castle(c, p, files) => {
+                self.castle_pieces(*c, *p, *files, false);
                 let cda_save = self.castles.clone();
                 // secretly hoping for compiler to optimize this (these are just bitwise ops)
                 self.castles.set(*p, Castle::Short, false);
@@ -270,7 +253,12 @@ impl Position {
                 self.fifty_mv += 1;
                 self.half_move_count += 1;
 
-                let r = task(&self, &Move::Castle(*c, *p));
+                // castle moves are filtered before, but in Chess960 the rook may have been
+                // shielding the king from a slider along the back rank
+                let legal = files.is_standard(*c)
+                    || self.pos.generate_attacks(p.other()) & self.pos[(*p, Piece::King)]
+                        == SpecialBB::Empty.declass();
+                let r = legal.then(|| task(&self, &Move::Castle(*c, *p, *files)));
 
                 self.half_move_count -= 1;
                 self.fifty_mv -= 1;
@@ -278,129 +266,427 @@ impl Position {
 
                 self.castles.copy_selection_player(*p, &cda_save);
 
-                match (c, p) {
-                    (Castle::Short, Player::White) => {
-                        self.pos.move_piece(
-                            Player::White,
-                            Piece::King,
-                            Square::g1.bb(),
-                            Square::e1.bb(),
-                        );
-                        self.pos.move_piece(
-                            Player::White,
-                            Piece::Rook,
-                            Square::f1.bb(),
-                            Square::h1.bb(),
-                        );
-                    }
-                    (Castle::Long, Player::White) => {
-                        self.pos.move_piece(
-                            Player::White,
-                            Piece::King,
-                            Square::c1.bb(),
-                            Square::e1.bb(),
-                        );
-                        self.pos.move_piece(
-                            Player::White,
-                            Piece::Rook,
-                            Square::d1.bb(),
-                            Square::a1.bb(),
-                        );
-                    }
-                    (Castle::Short, Player::Black) => {
-                        self.pos.move_piece(
-                            Player::Black,
-                            Piece::King,
-                            Square::g8.bb(),
-                            Square::e8.bb(),
-                        );
-                        self.pos.move_piece(
-                            Player::Black,
-                            Piece::Rook,
-                            Square::f8.bb(),
-                            Square::h8.bb(),
-                        );
+                self.castle_pieces(*c, *p, *files, true);
+                r
+            }
+        }
+    }
+
+    // moves the king and the rook of a castle, or puts them back
+    // in Chess960 one may land where the other stood: both are lifted before being put down
+    fn castle_pieces(&mut self, c: Castle, p: Player, files: CastleFiles, back: bool) {
+        let squares = castle_squares(c, p, files);
+        for (piece, src, dest) in squares {
+            self.pos
+                .remove_piece(p, piece, if back { dest } else { src });
+        }
+        for (piece, src, dest) in squares {
+            self.pos
+                .add_new_piece(p, piece, if back { src } else { dest });
+        }
+    }
+
+    // plays a pseudo-legal move in place, unstack takes it back
+    // promotion is the piece a pawn reaching the last rank turns into (None otherwise)
+    pub(crate) fn stack(&mut self, m: &Move, promotion: Option<Piece>) -> Unstack {
+        let turn = self.turn();
+        let mut undo = Unstack {
+            captured: None,
+            promoted: None,
+            castles: self.castles,
+            en_passant: self.en_passant,
+            fifty_mv: self.fifty_mv,
+        };
+        self.fifty_mv += 1;
+        self.half_move_count += 1;
+
+        match m {
+            Move::Normal(ch) => {
+                let src: Bitboard<Square> = ch.src.into();
+                let dest: Bitboard<Square> = ch.dest.into();
+
+                if ch.piece == Piece::Pawn
+                    && dest.declass() & self.en_passant != SpecialBB::Empty.declass()
+                {
+                    let target = match turn {
+                        Player::White => dest - 1,
+                        Player::Black => dest + 1,
                     }
-                    (Castle::Long, Player::Black) => {
-                        self.pos.move_piece(
-                            Player::Black,
-                            Piece::King,
-                            Square::c8.bb(),
-                            Square::e8.bb(),
-                        );
-                        self.pos.move_piece(
-                            Player::Black,
-                            Piece::Rook,
-                            Square::d8.bb(),
-                            Square::a8.bb(),
-                        );
+                    .into_iter()
+                    .next()
+                    .unwrap();
+                    self.pos.remove_piece(turn.other(), Piece::Pawn, target);
+                    undo.captured = Some((Piece::Pawn, target));
+                } else if let Some(cap) = self.pos.get((turn.other(), dest)) {
+                    self.fifty_mv = 0;
+                    self.pos.remove_piece(turn.other(), cap, dest);
+                    undo.captured = Some((cap, dest));
+                }
+
+                if ch.piece == Piece::Rook {
+                    self.castles.remove_rook(turn, src);
+                }
+                if ch.piece == Piece::King {
+                    self.castles.set(turn, Castle::Short, false);
+                    self.castles.set(turn, Castle::Long, false);
+                }
+                self.castles.remove_rook(turn.other(), dest);
+
+                self.en_passant = SpecialBB::Empty.declass();
+                if ch.piece == Piece::Pawn {
+                    self.fifty_mv = 0;
+                    if (ch.dest - 2) == ch.src.declass() {
+                        self.en_passant = ch.dest - 1;
+                    } else if (ch.dest + 2) == ch.src.declass() {
+                        self.en_passant = ch.dest + 1;
                     }
-                };
-                Some(r)
+                }
+
+                self.pos.move_piece(turn, ch.piece, src, dest);
+                if let Some(p) = promotion {
+                    self.pos.remove_piece(turn, Piece::Pawn, dest);
+                    self.pos.add_new_piece(turn, p, dest);
+                    undo.promoted = Some(p);
+                }
+            }
+            Move::Castle(c, p, files) => {
+                self.castle_pieces(*c, *p, *files, false);
+                self.castles.set(*p, Castle::Short, false);
+                self.castles.set(*p, Castle::Long, false);
+                self.en_passant = SpecialBB::Empty.declass();
+            }
+        }
+        undo
+    }
+
+    // takes back a move played with stack, m and undo must come from the matching call
+    pub(crate) fn unstack(&mut self, m: &Move, undo: Unstack) {
+        self.half_move_count -= 1;
+        let turn = self.turn();
+
+        match m {
+            Move::Normal(ch) => {
+                let src: Bitboard<Square> = ch.src.into();
+                let dest: Bitboard<Square> = ch.dest.into();
+                if let Some(p) = undo.promoted {
+                    self.pos.remove_piece(turn, p, dest);
+                    self.pos.add_new_piece(turn, Piece::Pawn, dest);
+                }
+                self.pos.move_piece(turn, ch.piece, dest, src);
+                if let Some((cap, sq)) = undo.captured {
+                    self.pos.add_new_piece(turn.other(), cap, sq);
+                }
             }
+            Move::Castle(c, p, files) => self.castle_pieces(*c, *p, *files, true),
         }
+
+        self.castles = undo.castles;
+        self.en_passant = undo.en_passant;
+        self.fifty_mv = undo.fifty_mv;
     }
 
     // very unoptimized, should not be called when we can access the move as &mv
-    pub fn getmove(&mut self, uci: &str) -> Result<Option<Move>, ()> {
-        let gather_value = |x: Option<Move>, y| x.or(y);
+    // Ok(None) when the move is not legal, an error if it is not UCI notation
+    pub fn getmove(&mut self, uci: &str) -> Result<Option<Move>, MoveError> {
+        let (from_to, promotion) =
+            parse_uci(uci).ok_or_else(|| MoveError::Notation(uci.to_string()))?;
+        Ok(AugmentedPos::list_issues(self)
+            .into_iter()
+            .find(|m| m.from_to() == from_to && m.promotion() == promotion))
+    }
+    // move written in standard algebraic notation, check marks and annotations allowed
+    // Ok(None) when no legal move, or more than one, matches it, an error if it is not SAN
+    pub fn getmove_san(&mut self, san: &str) -> Result<Option<Move>, MoveError> {
+        let written = san;
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        if !is_san(san) {
+            return Err(MoveError::Notation(written.to_string()));
+        }
+        let mut matching = self
+            .legal_moves()
+            .into_iter()
+            .filter(|m| m.to_san(self).trim_end_matches(['+', '#']) == san);
+        Ok(match (matching.next(), matching.next()) {
+            (Some(m), None) => Some(m),
+            _ => None,
+        })
+    }
+
+    pub fn playmove(mut self, uci: &str) -> Result<Option<Position>, MoveError> {
+        Ok(self.getmove(uci)?.map(|m| {
+            self.stack(&m, m.promotion());
+            self
+        }))
+    }
+
+    // play a sequence of UCI moves, stopping at the first one that is not legal
+    pub fn apply_uci_moves(&self, moves: &[impl AsRef<str>]) -> Result<Position, MoveError> {
+        self.apply_uci_moves_with_history(moves).map(|(pos, _)| pos)
+    }
+
+    // same, also returning the positions left behind for repetition detection
+    pub fn apply_uci_moves_with_history(
+        &self,
+        moves: &[impl AsRef<str>],
+    ) -> Result<(Position, RepetitionHistory), MoveError> {
+        let mut pos = *self;
+        let mut history = super::RepetitionHistory::default();
+        for (index, m) in moves.iter().enumerate() {
+            history.push(&pos);
+            pos = match pos.playmove(m.as_ref()) {
+                Ok(Some(p)) => p,
+                Ok(None) | Err(_) => {
+                    return Err(MoveError::Illegal {
+                        index,
+                        uci: m.as_ref().to_string(),
+                    });
+                }
+            };
+        }
+        Ok((pos, history))
+    }
+
+    pub fn from_startpos_moves(moves: &[&str]) -> Result<Position, MoveError> {
+        Self::startingpos().apply_uci_moves(moves)
+    }
+
+    // position holding exactly these pieces, no castle nor en passant, first move of the game
+    pub fn from_pieces(
+        pieces: &[(Player, Piece, Square)],
+        turn: Player,
+    ) -> Result<Position, SetupError> {
+        let mut pos = Self::empty();
+        pos.half_move_count = turn as u32;
+        for (pl, piece, sq) in pieces {
+            let sq = sq.bb();
+            if pos.occupied() & sq != SpecialBB::Empty.declass() {
+                return Err(SetupError::SquareTaken(sq));
+            }
+            if *piece == Piece::Pawn
+                && sq.declass() & (Rank::R1.bb() | Rank::R8) != SpecialBB::Empty.declass()
+            {
+                return Err(SetupError::PawnOnBackRank(sq));
+            }
+            pos.pos.add_new_piece(*pl, *piece, sq);
+        }
+        for pl in [Player::White, Player::Black] {
+            if pos.pos[(pl, Piece::King)].count() != 1 {
+                return Err(SetupError::KingCount(pl));
+            }
+        }
+        // the side that just moved cannot have left its king attacked
+        if AugmentedPos::check_legal(&pos).is_err() {
+            return Err(SetupError::OpponentInCheck);
+        }
+        Ok(pos)
+    }
+
+    // king and pawn against king, the pawn being white
+    pub fn kpk(
+        white_king: Square,
+        black_king: Square,
+        pawn: Square,
+        turn: Player,
+    ) -> Result<Position, SetupError> {
+        Self::kxk(white_king, black_king, Piece::Pawn, pawn, turn)
+    }
+
+    // king and rook against king, the rook being white
+    pub fn krk(
+        white_king: Square,
+        black_king: Square,
+        rook: Square,
+        turn: Player,
+    ) -> Result<Position, SetupError> {
+        Self::kxk(white_king, black_king, Piece::Rook, rook, turn)
+    }
 
-        let a = AugmentedPos::map_issues(
+    // king and queen against king, the queen being white
+    pub fn kqk(
+        white_king: Square,
+        black_king: Square,
+        queen: Square,
+        turn: Player,
+    ) -> Result<Position, SetupError> {
+        Self::kxk(white_king, black_king, Piece::Queen, queen, turn)
+    }
+
+    // king, bishop and knight against king, the minor pieces being white
+    pub fn kbnk(
+        white_king: Square,
+        black_king: Square,
+        bishop: Square,
+        knight: Square,
+        turn: Player,
+    ) -> Result<Position, SetupError> {
+        Self::from_pieces(
+            &[
+                (Player::White, Piece::King, white_king),
+                (Player::Black, Piece::King, black_king),
+                (Player::White, Piece::Bishop, bishop),
+                (Player::White, Piece::Knight, knight),
+            ],
+            turn,
+        )
+    }
+
+    fn kxk(
+        white_king: Square,
+        black_king: Square,
+        piece: Piece,
+        sq: Square,
+        turn: Player,
+    ) -> Result<Position, SetupError> {
+        Self::from_pieces(
+            &[
+                (Player::White, Piece::King, white_king),
+                (Player::Black, Piece::King, black_king),
+                (Player::White, piece, sq),
+            ],
+            turn,
+        )
+    }
+
+    // squares holding a piece of either color
+    pub fn occupied(&self) -> Bitboard<GenericBB> {
+        self.pos.occupied(Player::White) | self.pos.occupied(Player::Black)
+    }
+
+    pub fn occupied_by(&self, player: Player) -> Bitboard<GenericBB> {
+        self.pos.occupied(player)
+    }
+
+    // pieces of the player that cannot leave the line between their king and an enemy slider:
+    // the only piece in between, removing it would reveal the attacker
+    pub fn pinned_pieces(&self, player: Player) -> Bitboard<GenericBB> {
+        type Rays = fn(Bitboard<GenericBB>, Bitboard<GenericBB>) -> Bitboard<GenericBB>;
+        let mut pinned = SpecialBB::Empty.declass();
+        let Some(king) = self.pos[(player, Piece::King)].into_iter().next() else {
+            return pinned;
+        };
+        let opp = player.other();
+        let occupied = self.occupied();
+        let queens = self.pos[(opp, Piece::Queen)];
+        let lines: [(Rays, Bitboard<GenericBB>); 2] = [
+            (
+                movegen::attacks::generate_rooks,
+                self.pos[(opp, Piece::Rook)] | queens,
+            ),
+            (
+                movegen::attacks::generate_bishops,
+                self.pos[(opp, Piece::Bishop)] | queens,
+            ),
+        ];
+        for (rays, snipers) in lines {
+            let seen = rays(king.declass(), occupied);
+            for piece in seen & self.pos.occupied(player) {
+                let revealed = rays(king.declass(), occupied & !piece.declass()) & !seen & snipers;
+                if revealed != SpecialBB::Empty.declass() {
+                    pinned |= piece;
+                }
+            }
+        }
+        pinned
+    }
+
+    // squares the player's king can move to: not holding one of its pieces, and not attacked
+    // once the king has left its square, so that it cannot step back along a slider's line
+    pub fn king_safe_squares(&self, player: Player) -> Bitboard<GenericBB> {
+        let king = self.pos[(player, Piece::King)];
+        let Some(sq) = king.into_iter().next() else {
+            return SpecialBB::Empty.declass();
+        };
+        let blockers = self.occupied() & !king;
+        let attacked = match player.other() {
+            Player::White => self.pos.white().attacks(blockers),
+            Player::Black => self.pos.black().attacks(blockers),
+        };
+        movegen::attacks::generate_king(sq) & !self.pos.occupied(player) & !attacked
+    }
+
+    // number of half moves played since the start of the game
+    pub fn ply(&self) -> usize {
+        self.half_move_count as usize
+    }
+
+    // full move number of the FEN notation, starting at 1 and incremented after Black moves
+    pub fn full_move_number(&self) -> u32 {
+        self.half_move_count / 2 + 1
+    }
+
+    // legal moves of the given piece type, castles included for the king
+    pub fn legal_moves_for(&self, piece: Piece) -> Vec<Move> {
+        AugmentedPos::map_piece_issues(
             self,
-            |_p, m| match format!("{m}") == uci {
-                true => Some(*m),
-                false => None,
+            piece,
+            |_, m| vec![*m],
+            |mut a, mut b| {
+                a.append(&mut b);
+                a
             },
-            gather_value,
-        );
-        // Here, receiving Option<Option<>> would mean that
-        // AugmentedPos::map_issues can fail at two different levels:
-        // - outer Option: failed position exploration (filtered out by rules)
-        // - inner Option: match with query failed
-        // AugmentedPos now can collapse both Options as:
-        // - Some(None) would mean all legal positions explored but none matching uci
-        // - Some(Some()) would mean all legal positions explored with one matching uci
-        // - None would mean no legal positions explored (invalid state)
-        Ok(match a {
-            Some(x) => x,
-            None => None,
-        })
+        )
+        .unwrap_or_default()
     }
-    pub fn playmove(self, uci: &str) -> Result<Option<Position>, ()> {
-        let gather_value = |x: Option<Position>, y| x.or(y);
 
-        let a = AugmentedPos::map_issues(
-            &self,
-            |p, m| match format!("{m}") == uci {
-                true => Some(*p),
-                false => None,
-            },
-            gather_value,
-        );
-        // Here, receiving Option<Option<>> would mean that
-        // AugmentedPos::map_issues can fail at two different levels:
-        // - outer Option: failed position exploration (filtered out by rules)
-        // - inner Option: match with query failed
-        // AugmentedPos now can collapse both Options as:
-        // - Some(None) would mean all legal positions explored but none matching uci
-        // - Some(Some()) would mean all legal positions explored with one matching uci
-        // - None would mean no legal positions explored (invalid state)
-        Ok(match a {
-            Some(x) => x,
-            None => None,
-        })
+    // does the move take an opponent piece, en passant included
+    pub fn is_capture(&self, m: &Move) -> bool {
+        match m {
+            Move::Normal(x) => {
+                let dest: Bitboard<Square> = x.dest.into();
+                self.pos.get((self.turn().other(), dest)).is_some()
+                    || (x.piece == Piece::Pawn
+                        && dest.declass() & self.en_passant != SpecialBB::Empty.declass())
+            }
+            Move::Castle(_, _, _) => false,
+        }
+    }
+
+    pub fn is_promotion(&self, m: &Move) -> bool {
+        match m {
+            Move::Normal(x) => {
+                x.piece == Piece::Pawn
+                    && x.dest.declass() & self.turn().other().backrank()
+                        != SpecialBB::Empty.declass()
+            }
+            Move::Castle(_, _, _) => false,
+        }
+    }
+
+    // legal moves, promotions listed once per promotion piece
+    // the order is unspecified and may change, sort them if it matters
+    pub fn legal_moves(&self) -> Vec<Move> {
+        AugmentedPos::list_issues(self)
+    }
+
+    // promotions counted once per promotion piece, as perft does
+    pub fn legal_move_count(&self) -> usize {
+        AugmentedPos::map_issues(self, |_, _| 1, |a, b| a + b).unwrap_or(0)
+    }
+
+    // legal moves split into (captures and promotions, quiet moves), to try the noisy ones first
+    pub fn staged_moves(&self) -> (Vec<Move>, Vec<Move>) {
+        self.legal_moves()
+            .into_iter()
+            .partition(|m| self.is_capture(m) || self.is_promotion(m))
+    }
+
+    // legal moves taking an opponent piece, promotions listed once per promotion piece
+    pub fn legal_captures(&self) -> Vec<Move> {
+        let (noisy, _) = self.staged_moves();
+        noisy.into_iter().filter(|m| self.is_capture(m)).collect()
     }
 
     // extract fen, knowing it is the first element in the iterator
+    // only its fields are consumed, leaving the iterator on what follows (e.g. moves)
     pub fn extract_fen(words: &mut std::str::SplitWhitespace<'_>) -> Option<Self> {
-        Self::parse_fen(
-            words.nth(0),
-            words.nth(0),
-            words.nth(0),
-            words.nth(0),
-            words.nth(0),
-            words.nth(0),
-        )
+        let (a, b, c, d) = (words.next(), words.next(), words.next(), words.next());
+        // the move counters are optional, "moves" may directly follow the en passant field
+        let mut counter = || {
+            words.clone().next()?.parse::<u32>().ok()?;
+            words.next()
+        };
+        let (e, f) = (counter(), counter());
+        Self::parse_fen(a, b, c, d, e, f)
     }
 
     pub fn parse_fen(
@@ -469,26 +755,49 @@ impl Position {
         debug_assert_eq!(sq_index, 8);
 
         let full_moves = full_moves
-            .parse::<u16>()
+            .parse::<u32>()
             .expect("Incorrect fen + bad handling = exception :/ [full moves]");
         let turn = match turn {
             "w" => Player::White,
             "b" => Player::Black,
             _ => panic!("Incorrect turn parameter in fen description ({})", turn),
         };
-        pos.half_move_count = 2 * full_moves + turn as u16;
+        // the full move number starts at 1, a 0 is tolerated as the first move
+        pos.half_move_count = 2 * full_moves.saturating_sub(1) + turn as u32;
         pos.fifty_mv = hf_mv_until_100
             .parse::<u16>()
             .expect("Incorrect input for fifty move rule");
 
+        // standard or X-FEN/Shredder-FEN rook files, a letter that is neither is skipped
         for c in castles.chars() {
-            match c {
-                '-' => break,
-                'K' => pos.castles.set(Player::White, Castle::Short, true),
-                'Q' => pos.castles.set(Player::White, Castle::Long, true),
-                'k' => pos.castles.set(Player::Black, Castle::Short, true),
-                'q' => pos.castles.set(Player::Black, Castle::Long, true),
-                _ => panic!("Incorrect castling rights in fen description ({})", castles),
+            match CastleData::xfen_right(c, &pos) {
+                Some((pl, side, file)) => {
+                    pos.castles.set(pl, side, true);
+                    pos.castles.set_rook_file(pl, side, file);
+                }
+                None if c == '-' => break,
+                None => log::warn!("Ignored castling right {c} in fen description ({castles})"),
+            }
+        }
+        // drop the rights whose king or rook has left the back rank, or which do not have the
+        // rook on their side of the king (the king may stand on any file in Chess960)
+        for pl in [Player::White, Player::Black] {
+            let king = (pos.pos[(pl, Piece::King)] & pl.backrank())
+                .into_iter()
+                .next();
+            for c in [Castle::Short, Castle::Long] {
+                let rook = pos.castles.rook_file(pl, c);
+                let rook_found = pos.pos[(pl, Piece::Rook)]
+                    & pl.backrank()
+                    & (File::A.declass() >> rook as usize)
+                    != SpecialBB::Empty.declass();
+                let on_its_side = king.is_some_and(|k| match c {
+                    Castle::Short => rook > k.to_index() % 8,
+                    Castle::Long => rook < k.to_index() % 8,
+                });
+                if !rook_found || !on_its_side {
+                    pos.castles.set(pl, c, false);
+                }
             }
         }
 
@@ -499,13 +808,365 @@ impl Position {
 
         pos
     }
+
+    // the six fields of the FEN, from_fen reads them back into the same position
+    pub fn to_fen(&self) -> String {
+        const PIECES: [(Piece, char); 6] = [
+            (Piece::Pawn, 'p'),
+            (Piece::Knight, 'n'),
+            (Piece::Bishop, 'b'),
+            (Piece::Rook, 'r'),
+            (Piece::Queen, 'q'),
+            (Piece::King, 'k'),
+        ];
+        let mut placement = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let sq = Bitboard::from_index(8 * rank + file);
+                let found = [Player::White, Player::Black].into_iter().find_map(|pl| {
+                    let p = self.pos.get((pl, sq))?;
+                    let c = PIECES.iter().find(|(x, _)| *x == p)?.1;
+                    Some(match pl {
+                        Player::White => c.to_ascii_uppercase(),
+                        Player::Black => c,
+                    })
+                });
+                match found {
+                    Some(c) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(c);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                placement.push('/');
+            }
+        }
+        let turn = match self.turn() {
+            Player::White => "w",
+            Player::Black => "b",
+        };
+        let en_passant = match self.en_passant.into_iter().next() {
+            Some(sq) => format!("{sq}"),
+            None => "-".to_string(),
+        };
+        format!(
+            "{placement} {turn} {} {en_passant} {} {}",
+            self.castles,
+            self.fifty_mv,
+            self.full_move_number()
+        )
+    }
+}
+
+/// Error raised when a move, or a move list, cannot be played
+#[derive(Clone, Debug, PartialEq)]
+pub enum MoveError {
+    // the move at this index of the list is not legal in the position reached
+    Illegal { index: usize, uci: String },
+    // neither UCI nor SAN notation, as expected
+    Notation(String),
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::Illegal { index, uci } => write!(f, "illegal move {uci} (move {index})"),
+            MoveError::Notation(m) => write!(f, "unreadable move {m}"),
+        }
+    }
+}
+
+/// Error raised when pieces cannot be set up into a legal position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetupError {
+    SquareTaken(Bitboard<Square>),
+    PawnOnBackRank(Bitboard<Square>),
+    // each side needs exactly one king
+    KingCount(Player),
+    // the king of the side that just moved is attacked
+    OpponentInCheck,
+}
+
+impl Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetupError::SquareTaken(sq) => write!(f, "two pieces on {sq}"),
+            SetupError::PawnOnBackRank(sq) => write!(f, "pawn on the back rank ({sq})"),
+            SetupError::KingCount(pl) => write!(f, "{pl:?} needs exactly one king"),
+            SetupError::OpponentInCheck => write!(f, "the side not to move is in check"),
+        }
+    }
+}
+
+// well formed SAN, without check marks: castles, or [piece][file][rank][x]square[=promotion]
+fn is_san(san: &str) -> bool {
+    if san == "O-O" || san == "O-O-O" {
+        return true;
+    }
+    let (mv, promotion) = match san.split_once('=') {
+        Some((mv, p)) => (mv, Some(p)),
+        None => (san, None),
+    };
+    if promotion.is_some_and(|p| !matches!(p, "N" | "B" | "R" | "Q")) {
+        return false;
+    }
+    let mv = mv.strip_prefix(['N', 'B', 'R', 'Q', 'K']).unwrap_or(mv);
+    let Some(split) = mv.len().checked_sub(2).filter(|i| mv.is_char_boundary(*i)) else {
+        return false;
+    };
+    let (from, dest) = mv.split_at(split);
+    let file = |c: char| ('a'..='h').contains(&c);
+    let rank = |c: char| ('1'..='8').contains(&c);
+    let from = from.strip_suffix('x').unwrap_or(from);
+    let from = from.strip_prefix(file).unwrap_or(from);
+    let from = from.strip_prefix(rank).unwrap_or(from);
+    let mut dest = dest.chars();
+    from.is_empty() && dest.next().is_some_and(file) && dest.next().is_some_and(rank)
+}
+
+// origin and destination square indices and promotion piece of a move in UCI notation
+fn parse_uci(uci: &str) -> Option<((u8, u8), Option<Piece>)> {
+    let mut chars = uci.chars();
+    let mut square = || {
+        let file = chars.next().filter(|c| ('a'..='h').contains(c))?;
+        let rank = chars.next().filter(|c| ('1'..='8').contains(c))?;
+        Some(8 * (rank as u8 - b'1') + (file as u8 - b'a'))
+    };
+    let from_to = (square()?, square()?);
+    let promotion = match (chars.next(), chars.next()) {
+        (None, _) => None,
+        (Some(c @ ('n' | 'b' | 'r' | 'q')), None) => Piece::from_notation(c).map(|(_, p)| p),
+        _ => return None,
+    };
+    Some((from_to, promotion))
+}
+
+/// Positions played before the current one, oldest first
+///
+/// Only the keys since the last capture or pawn move matter, older positions cannot come back.
+#[derive(Clone, Debug, Default)]
+pub struct RepetitionHistory {
+    // (key, fifty move counter) of each position
+    keys: Vec<(usize, u16)>,
+}
+
+impl RepetitionHistory {
+    pub fn push(&mut self, pos: &Position) {
+        self.keys.push((pos.repetition_key(), pos.fifty_mv));
+    }
+
+    pub fn pop(&mut self) {
+        self.keys.pop();
+    }
+
+    pub fn clear(&mut self) {
+        self.keys.clear();
+    }
+
+    // would pos, next to play after the last position pushed, be its third occurrence
+    // positions with the other side to move never match, they are skipped
+    pub fn is_threefold(&self, pos: &Position) -> bool {
+        let hash = pos.repetition_key();
+        let mut count = 0;
+        for (i, (key, fifty_mv)) in self.keys.iter().rev().enumerate() {
+            if i % 2 == 1 && *key == hash {
+                count += 1;
+            }
+            // reached by a capture or a pawn move, nothing before can repeat
+            if *fifty_mv == 0 {
+                break;
+            }
+        }
+        count >= 2
+    }
+}
+
+////// Terminal detection
+
+/// Status of a position regarding the end of the game
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate(Player), // winner
+    Stalemate,
+    DrawFiftyMove,
+    DrawRepetition,
+    DrawInsufficientMaterial,
+}
+
+impl Display for GameStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameStatus::Ongoing => write!(f, "ongoing"),
+            GameStatus::Checkmate(Player::White) => write!(f, "checkmate (White wins)"),
+            GameStatus::Checkmate(Player::Black) => write!(f, "checkmate (Black wins)"),
+            GameStatus::Stalemate => write!(f, "stalemate"),
+            GameStatus::DrawFiftyMove => write!(f, "draw by fifty-move rule"),
+            GameStatus::DrawRepetition => write!(f, "draw by threefold repetition"),
+            GameStatus::DrawInsufficientMaterial => write!(f, "draw by insufficient material"),
+        }
+    }
+}
+
+impl Position {
+    // is the player to move in check
+    pub fn is_check(&self) -> bool {
+        let turn = self.turn();
+        self.pos.generate_attacks(turn.other()) & self.pos[(turn, Piece::King)]
+            != SpecialBB::Empty.declass()
+    }
+
+    // would playing this pseudo-legal move leave the mover's king attacked
+    pub fn in_check_after(&self, m: &Move) -> bool {
+        let turn = self.turn();
+        let mut p = *self;
+        // the promoted piece does not matter, it blocks the same lines
+        p.stack(m, movegen::promotion_choices(m, turn)[0]);
+        p.pos.generate_attacks(turn.other()) & p.pos[(turn, Piece::King)]
+            != SpecialBB::Empty.declass()
+    }
+
+    // leaf condition of the quiescence search: not in check and nothing to capture
+    pub fn is_quiet(&self) -> bool {
+        !self.is_check() && self.legal_captures().is_empty()
+    }
+
+    pub fn has_legal_moves(&self) -> bool {
+        AugmentedPos::map_issues(self, |_, _| (), |_, _| ()).is_some()
+    }
+
+    // neither side can mate : K vs K, K+minor vs K, or bishops all on the same square color
+    pub fn is_insufficient_material(&self) -> bool {
+        const LIGHT_SQUARES: Bitboard<GenericBB> = Bitboard(GenericBB(0x55AA55AA55AA55AA));
+
+        let heavy = [Piece::Pawn, Piece::Rook, Piece::Queen]
+            .iter()
+            .map(|p| self.pos[(Player::White, *p)] | self.pos[(Player::Black, *p)])
+            .fold(SpecialBB::Empty.declass(), |a, b| a | b);
+        if heavy != SpecialBB::Empty.declass() {
+            return false;
+        }
+        let knights =
+            self.pos[(Player::White, Piece::Knight)] | self.pos[(Player::Black, Piece::Knight)];
+        let bishops =
+            self.pos[(Player::White, Piece::Bishop)] | self.pos[(Player::Black, Piece::Bishop)];
+        let minors = (knights | bishops).count();
+
+        minors <= 1
+            || (knights == SpecialBB::Empty.declass()
+                && (bishops & LIGHT_SQUARES == SpecialBB::Empty.declass()
+                    || bishops & !LIGHT_SQUARES == SpecialBB::Empty.declass()))
+    }
+
+    // pieces of each side from the king down, white first: "KQvKR"
+    pub fn material_signature(&self) -> String {
+        const ORDER: [(Piece, char); 6] = [
+            (Piece::King, 'K'),
+            (Piece::Queen, 'Q'),
+            (Piece::Rook, 'R'),
+            (Piece::Bishop, 'B'),
+            (Piece::Knight, 'N'),
+            (Piece::Pawn, 'P'),
+        ];
+        let side = |pl: Player| -> String {
+            ORDER
+                .iter()
+                .flat_map(|(p, c)| {
+                    let n = self.pos[(pl, *p)].to_bb64().count_ones() as usize;
+                    std::iter::repeat_n(*c, n)
+                })
+                .collect()
+        };
+        format!("{}v{}", side(Player::White), side(Player::Black))
+    }
+
+    // castling rights of a Chess960 start: the king off the E file, or a rook off the corners
+    pub fn is_chess960(&self) -> bool {
+        [Player::White, Player::Black].into_iter().any(|pl| {
+            let Some(king) = self.pos[(pl, Piece::King)].into_iter().next() else {
+                return false;
+            };
+            [Castle::Short, Castle::Long].into_iter().any(|c| {
+                self.castles.fetch(pl, c) && !self.castles.files(pl, c, king).is_standard(c)
+            })
+        })
+    }
+
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.fifty_mv >= 100
+    }
+
+    // score of any draw (repetition, fifty moves, stalemate, insufficient material) for a search
+    // played by engine, who values a draw contempt centipawns below an equal position
+    pub fn material_draw_score(engine: Player, contempt: i32, depth: usize) -> Eval {
+        let cp = match engine {
+            Player::White => -contempt,
+            Player::Black => contempt,
+        };
+        Eval::Approx(ApproxEval::new(cp, depth))
+    }
+
+    // third occurrence of the position, given the positions of the game played before it
+    pub fn threefold_in_game(&self, history: &RepetitionHistory) -> bool {
+        history.is_threefold(self)
+    }
+
+    // identity of the position for the repetition rule: the same pieces on the same squares,
+    // the same side to move, and the same castling and en passant rights
+    pub fn repetition_key(&self) -> usize {
+        self.hash()
+            ^ self.castles.hash().wrapping_mul(4654987)
+            ^ (self.en_passant.to_bb64() as usize).wrapping_mul(6541653246798795667)
+            ^ (self.turn() as usize).wrapping_mul(9897995300789921388)
+    }
+
+    // mate and stalemate take precedence over the draw rules
+    // history holds the positions of the game played before this one
+    pub fn status(&self, history: &RepetitionHistory) -> GameStatus {
+        if !self.has_legal_moves() {
+            match self.is_check() {
+                true => GameStatus::Checkmate(self.turn().other()),
+                false => GameStatus::Stalemate,
+            }
+        } else if self.is_insufficient_material() {
+            GameStatus::DrawInsufficientMaterial
+        } else if self.is_fifty_move_draw() {
+            GameStatus::DrawFiftyMove
+        } else if self.threefold_in_game(history) {
+            GameStatus::DrawRepetition
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+
+    // any draw: stalemate, threefold repetition, fifty-move rule or insufficient material
+    // a mate delivered on the hundredth half move still wins
+    pub fn is_draw(&self, history: &RepetitionHistory) -> bool {
+        match self.status(history) {
+            GameStatus::Ongoing | GameStatus::Checkmate(_) => false,
+            GameStatus::Stalemate
+            | GameStatus::DrawFiftyMove
+            | GameStatus::DrawRepetition
+            | GameStatus::DrawInsufficientMaterial => true,
+        }
+    }
 }
 
 ////// Print functions
 
 impl Position {
     // TODO: replace with fen interpretation / or other
-    pub fn pretty_print<O: UciOutputStream>(&self) {
+    // history holds the positions of the game played before this one, for the status line
+    pub fn pretty_print<O: UciOutputStream>(&self, history: &RepetitionHistory) {
         debug_assert_eq!(File::G.declass() & Rank::R5, Square::g5.declass());
 
         let repr = [['♟', '♞', '♝', '♜', '♛', '♚'], [
@@ -521,14 +1182,13 @@ impl Position {
             let mut s = format!("┃");
             for file in 0..8 {
                 s = format!("{s} ");
-                let bb_sq = Bitboard(GenericBB(1 << (8 * (7 - rank) + file)));
+                let bb_sq = File::from_index(file).declass() & Rank::from_index(7 - rank);
                 let mut printed = false;
                 // only one in bb_sq but this is for safety
                 for sq in bb_sq {
                     for pl in 0..2 {
-                        for pc in 0..Piece::COUNT {
+                        for pc in enum_iterator::all::<Piece>() {
                             let pl = Player::from_usize(pl).other();
-                            let pc = Piece::from_usize(pc).unwrap();
                             if self.pos[(pl, pc)] & sq != SpecialBB::Empty.declass() {
                                 printed = true;
                                 s = format!("{s}{}", repr[pl as usize][pc as usize]);
@@ -562,6 +1222,23 @@ impl Position {
             "  a   b   c   d   e   f   g   h  ",
         ))
         .unwrap();
+        O::send_response(crate::uci::UciResponse::Debug(
+            format!("Status: {}", self.status(history)).as_str(),
+        ))
+        .unwrap();
+        let en_passant = match self.en_passant.into_iter().next() {
+            Some(sq) => format!("{sq}"),
+            None => "-".to_string(),
+        };
+        for line in [
+            format!("fifty_mv: {}", self.fifty_mv),
+            format!("fullmove: {}", self.full_move_number()),
+            format!("en_passant: {en_passant}"),
+            format!("castling: {}", self.castles),
+            format!("key: {:016X}", self.hash()),
+        ] {
+            O::send_response(crate::uci::UciResponse::Debug(line.as_str())).unwrap();
+        }
         log::info!("{:#?}", self);
     }
 }
@@ -584,11 +1261,151 @@ mod tests {
         });
     }
 
-    /*#[test]
-    fn zobrist() {
+    #[cfg(feature = "perft")]
+    #[bench]
+    fn perft_startpos_5(b: &mut Bencher) {
+        use crate::uci::NullUciStream;
         let mut a = super::Position::startingpos();
-        let ml = super::AugmentedPos::list_issues(&a).unwrap();
-        let initial_hash = a.hash();
+        b.iter(|| {
+            assert_eq!(
+                a.perft_top::<NullUciStream>(std::hint::black_box(5)),
+                4865609
+            );
+        });
+    }
+
+    #[cfg(feature = "perft")]
+    #[bench]
+    fn perft_startpos_5_cached(b: &mut Bencher) {
+        use crate::{tt::PerftCache, uci::NullUciStream};
+        let mut a = super::Position::startingpos();
+        b.iter(|| {
+            // a warm cache would answer at the root
+            let mut cache = PerftCache::new(1 << 16);
+            assert_eq!(
+                a.perft_top_cached::<NullUciStream>(std::hint::black_box(5), &mut cache),
+                4865609
+            );
+        });
+    }
+
+    #[cfg(feature = "perft")]
+    #[bench]
+    fn perft_startpos_5_parallel(b: &mut Bencher) {
+        use crate::uci::NullUciStream;
+        let mut a = super::Position::startingpos();
+        b.iter(|| {
+            assert_eq!(
+                a.perft_top_parallel::<NullUciStream>(std::hint::black_box(5), 4),
+                4865609
+            );
+        });
+    }
+
+    #[bench]
+    fn perft_startpos_4_copy(b: &mut Bencher) {
+        let a = super::Position::startingpos();
+        b.iter(|| assert_eq!(a.perft(std::hint::black_box(4)), 197281));
+    }
+
+    #[bench]
+    fn perft_startpos_4_in_place(b: &mut Bencher) {
+        let mut a = super::Position::startingpos();
+        b.iter(|| assert_eq!(a.perft_in_place(std::hint::black_box(4)), 197281));
+    }
+
+    #[test]
+    fn perft_in_place_matches_copy() {
+        let suite = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                4,
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                3,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                3,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                3,
+            ),
+            (
+                "r3k2r/ppp2ppp/2n1bn2/2b1p3/4P3/2N2N2/PPPP1PPP/R1B1KB1R w KQkq - 0 1",
+                3,
+            ),
+            ("k7/8/8/8/8/8/P7/7K w - - 0 1", 5),
+            ("k7/8/8/8/8/8/B7/7K w - - 0 1", 5),
+        ];
+        for (fen, depth) in suite {
+            let p = Position::extract_fen(&mut fen.split_whitespace()).unwrap();
+            let mut q = p;
+            for d in 0..=depth {
+                assert_eq!(q.perft_in_place(d), p.perft(d), "{fen} depth {d}");
+            }
+            // handed back unchanged
+            assert!(q == p, "{fen}");
+        }
+    }
+
+    #[cfg(feature = "perft")]
+    #[test]
+    fn perft_cached_totals() {
+        use crate::tt::PerftCache;
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let kiwipete = Position::extract_fen(&mut kiwipete.split_whitespace()).unwrap();
+        for (mut p, depth) in [(Position::startingpos(), 4), (kiwipete, 3)] {
+            let expected = p.perft_top::<NullUciStream>(depth);
+            let mut cache = PerftCache::new(1 << 12);
+            assert_eq!(
+                p.perft_top_cached::<NullUciStream>(depth, &mut cache),
+                expected
+            );
+            // entries of every depth are in the cache now, none is served at the wrong one
+            for d in 1..=depth {
+                assert_eq!(
+                    p.perft_top_cached::<NullUciStream>(d, &mut cache),
+                    p.perft(d)
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "perft")]
+    #[test]
+    fn perft_parallel_totals() {
+        use crate::uci::CaptureUciStream;
+
+        let opening = "r3k2r/ppp2ppp/2n1bn2/2b1p3/4P3/2N2N2/PPPP1PPP/R1B1KB1R w KQkq - 0 1";
+        let opening = Position::extract_fen(&mut opening.split_whitespace()).unwrap();
+        for (mut p, depth) in [(Position::startingpos(), 5), (opening, 4)] {
+            let expected = p.perft_in_place(depth);
+            for threads in [1, 3, 8] {
+                assert_eq!(
+                    p.perft_top_parallel::<NullUciStream>(depth, threads),
+                    expected
+                );
+            }
+        }
+        // the divide lines come in the order of the sequential perft
+        let mut p = Position::startingpos();
+        CaptureUciStream::take();
+        p.perft_top::<CaptureUciStream>(2);
+        let sequential = CaptureUciStream::take();
+        p.perft_top_parallel::<CaptureUciStream>(2, 4);
+        assert_eq!(CaptureUciStream::take(), sequential);
+    }
+
+    /*#[test]
+    fn zobrist() {
+        let mut a = super::Position::startingpos();
+        let ml = super::AugmentedPos::list_issues(&a).unwrap();
+        let initial_hash = a.hash();
         for m in ml.iter() {
             a.stack(m);
             assert_ne!(
@@ -605,6 +1422,347 @@ mod tests {
         );
     }*/
 
+    #[test]
+    fn perft_silent() {
+        let p = Position::startingpos();
+        crate::uci::CaptureUciStream::take();
+        assert_eq!(p.perft(3), 8902);
+        assert!(crate::uci::CaptureUciStream::take().is_empty());
+    }
+
+    #[test]
+    fn startpos_moves() {
+        let p = Position::from_startpos_moves(&["e2e4", "e7e5"]).unwrap();
+        let expected = Position::from_fen(
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR",
+            "w",
+            "KQkq",
+            "e6",
+            "0",
+            "2",
+        );
+        assert!(p == expected);
+        assert_eq!(
+            Position::from_startpos_moves(&["e2e4", "e2e4"]).err(),
+            Some(super::MoveError::Illegal {
+                index: 1,
+                uci: "e2e4".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn extract_fen_stops_at_moves() {
+        let line = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 moves e2e4";
+        let mut words = line.split_whitespace();
+        let p = Position::extract_fen(&mut words).unwrap();
+        assert!(p == Position::startingpos());
+        assert_eq!(words.collect::<Vec<_>>(), ["moves", "e2e4"]);
+
+        // without move counters
+        let line = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - moves e2e4";
+        let mut words = line.split_whitespace();
+        let p = Position::extract_fen(&mut words).unwrap();
+        assert!(p == Position::startingpos());
+        assert_eq!(words.collect::<Vec<_>>(), ["moves", "e2e4"]);
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 12 40",
+            "7k/8/8/8/8/8/8/K7 b - - 99 120",
+        ] {
+            let mut words = fen.split_whitespace();
+            let p = Position::extract_fen(&mut words).unwrap();
+            assert_eq!(p.to_fen(), fen);
+        }
+        assert_eq!(
+            Position::from_startpos_moves(&["e2e4"]).unwrap().to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+    }
+
+    #[test]
+    fn describe_moves() {
+        let p = Position::from_startpos_moves(&["e2e4", "d7d5"]).unwrap();
+        let (noisy, quiet) = p.staged_moves();
+        let describe = |uci: &str| {
+            let m = noisy.iter().chain(quiet.iter());
+            m.into_iter()
+                .find(|m| format!("{m}") == uci)
+                .unwrap()
+                .describe(&p)
+        };
+        assert_eq!(describe("e4d5"), "White Pawn e4→d5 (capture: Pawn)");
+        assert_eq!(describe("g1f3"), "White Knight g1→f3 (capture: none)");
+    }
+
+    #[test]
+    fn draw_conditions() {
+        use crate::position::RepetitionHistory;
+
+        let none = RepetitionHistory::default();
+        assert!(!Position::startingpos().is_draw(&none));
+
+        // stalemate
+        let p = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8", "b", "-", "-", "0", "1");
+        assert!(p.is_draw(&none));
+        // fifty-move rule, unless mated
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/R3K3", "w", "-", "-", "100", "80");
+        assert!(p.is_draw(&none));
+        let p = Position::from_fen("R3k3/8/4K3/8/8/8/8/8", "b", "-", "-", "100", "80");
+        assert!(!p.is_draw(&none));
+        // insufficient material
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/4KN2", "w", "-", "-", "0", "1");
+        assert!(p.is_draw(&none));
+
+        // threefold repetition, knights back and forth
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2);
+        for i in 0..shuffle.len() {
+            let (p, history) = Position::startingpos()
+                .apply_uci_moves_with_history(&shuffle[..i])
+                .unwrap();
+            assert!(!p.is_draw(&history), "after {i} moves");
+        }
+        let (p, history) = Position::startingpos()
+            .apply_uci_moves_with_history(&shuffle)
+            .unwrap();
+        assert!(p.is_draw(&history));
+    }
+
+    #[test]
+    fn game_status() {
+        use crate::position::{GameStatus, RepetitionHistory};
+        use crate::prelude::Player;
+
+        let none = RepetitionHistory::default();
+        assert_eq!(Position::startingpos().status(&none), GameStatus::Ongoing);
+
+        let fools_mate = ["f2f3", "e7e5", "g2g4", "d8h4"];
+        let p = Position::from_startpos_moves(&fools_mate).unwrap();
+        assert_eq!(p.status(&none), GameStatus::Checkmate(Player::Black));
+        let p = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8", "b", "-", "-", "0", "1");
+        assert_eq!(p.status(&none), GameStatus::Stalemate);
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/R3K3", "w", "-", "-", "100", "80");
+        assert_eq!(p.status(&none), GameStatus::DrawFiftyMove);
+        // mated on the hundredth half move
+        let p = Position::from_fen("R3k3/8/4K3/8/8/8/8/8", "b", "-", "-", "100", "80");
+        assert_eq!(p.status(&none), GameStatus::Checkmate(Player::White));
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/4KN2", "w", "-", "-", "0", "1");
+        assert_eq!(p.status(&none), GameStatus::DrawInsufficientMaterial);
+
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let moves: Vec<&str> = shuffle.iter().cycle().take(8).copied().collect();
+        let (p, history) = Position::startingpos()
+            .apply_uci_moves_with_history(&moves[..7])
+            .unwrap();
+        assert_eq!(p.status(&history), GameStatus::Ongoing);
+        let (p, history) = Position::startingpos()
+            .apply_uci_moves_with_history(&moves)
+            .unwrap();
+        assert_eq!(p.status(&history), GameStatus::DrawRepetition);
+        assert_eq!(
+            format!("{}", p.status(&history)),
+            "draw by threefold repetition"
+        );
+
+        // the rooks coming back to a1 and a8 lost the queenside castling rights, the starting
+        // position does not count as one more occurrence
+        let p = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R", "w", "KQkq", "-", "0", "1");
+        let rooks = ["a1b1", "a8b8", "b1a1", "b8a8"];
+        let (q, history) = p.apply_uci_moves_with_history(&rooks.repeat(2)).unwrap();
+        assert_eq!(q.status(&history), GameStatus::Ongoing);
+        let (q, history) = p.apply_uci_moves_with_history(&rooks.repeat(3)).unwrap();
+        assert_eq!(q.status(&history), GameStatus::DrawRepetition);
+    }
+
+    #[test]
+    fn material_signature() {
+        let p = Position::from_fen("3rk3/8/8/8/8/8/8/3QK3", "w", "-", "-", "0", "1");
+        assert_eq!(p.material_signature(), "KQvKR");
+        assert_eq!(
+            Position::startingpos().material_signature(),
+            "KQRRBBNNPPPPPPPPvKQRRBBNNPPPPPPPP"
+        );
+    }
+
+    #[test]
+    fn repetition_history() {
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let moves: Vec<&str> = shuffle.iter().cycle().take(8).copied().collect();
+        let (p, history) = Position::startingpos()
+            .apply_uci_moves_with_history(&moves)
+            .unwrap();
+        assert!(history.is_threefold(&p));
+        assert!(p.threefold_in_game(&history));
+        let (p, history) = Position::startingpos()
+            .apply_uci_moves_with_history(&moves[..4])
+            .unwrap();
+        assert!(!history.is_threefold(&p));
+        assert!(!p.threefold_in_game(&history));
+
+        // positions before an irreversible move are not compared
+        let fen = |board, turn, fifty| Position::from_fen(board, turn, "-", "-", fifty, "1");
+        let a = "4k3/8/8/8/8/8/8/4K1N1";
+        let b = "4k3/8/8/8/8/5N2/8/4K3";
+        let mut history = super::RepetitionHistory::default();
+        for p in [fen(a, "w", "4"), fen(b, "b", "5"), fen(a, "w", "6")] {
+            history.push(&p);
+        }
+        history.push(&fen(b, "b", "7"));
+        assert!(history.is_threefold(&fen(a, "w", "8")));
+        history.pop();
+        history.push(&fen(b, "b", "0"));
+        assert!(!history.is_threefold(&fen(a, "w", "1")));
+
+        // the position after 1. e4 e5 still had every castling right: the kings shuffling
+        // back to e1 and e8 only make the second occurrence of the one without them
+        let shuffle = ["e1e2", "e8e7", "e2e1", "e7e8"];
+        let mut moves = vec!["e2e4", "e7e5"];
+        moves.extend(shuffle.repeat(2));
+        let (p, history) = Position::startingpos()
+            .apply_uci_moves_with_history(&moves)
+            .unwrap();
+        assert!(!history.is_threefold(&p));
+        moves.extend(shuffle);
+        let (p, history) = Position::startingpos()
+            .apply_uci_moves_with_history(&moves)
+            .unwrap();
+        assert!(history.is_threefold(&p));
+    }
+
+    #[test]
+    fn pinned_pieces() {
+        use crate::prelude::*;
+
+        // knight pinned by a bishop, the rook next to the king is free
+        let p = Position::from_fen("4k3/8/8/b7/8/2N5/8/3RK3", "w", "-", "-", "0", "1");
+        assert_eq!(p.pinned_pieces(Player::White), Square::c3.declass());
+        assert_eq!(p.pinned_pieces(Player::Black), SpecialBB::Empty.declass());
+        // two pieces on the line, neither is pinned
+        let p = Position::from_fen("4k3/4r3/8/4B3/8/8/4R3/4K3", "w", "-", "-", "0", "1");
+        assert_eq!(p.pinned_pieces(Player::White), SpecialBB::Empty.declass());
+        assert_eq!(
+            Position::startingpos().pinned_pieces(Player::White),
+            SpecialBB::Empty.declass()
+        );
+    }
+
+    #[test]
+    fn king_safe_squares() {
+        use crate::prelude::*;
+
+        // rook check along the rank: f4, behind the king, is attacked too
+        let p = Position::from_fen("4k3/8/8/8/r3K3/8/8/8", "w", "-", "-", "0", "1");
+        let expected = Square::d3.declass()
+            | Square::e3.declass()
+            | Square::f3.declass()
+            | Square::d5.declass()
+            | Square::e5.declass()
+            | Square::f5.declass();
+        assert_eq!(p.king_safe_squares(Player::White), expected);
+        assert_eq!(p.legal_moves_for(Piece::King).len(), 6);
+        // own pieces are excluded, the opponent's capturable ones are not
+        let p = Position::from_fen("4k3/8/8/8/r3K3/3P4/5n2/8", "w", "-", "-", "0", "1");
+        assert_eq!(
+            p.king_safe_squares(Player::White),
+            expected & !Square::d3.declass()
+        );
+        assert_eq!(
+            Position::startingpos().king_safe_squares(Player::White),
+            SpecialBB::Empty.declass()
+        );
+    }
+
+    #[test]
+    fn occupancy() {
+        use crate::prelude::*;
+
+        let p = Position::startingpos();
+        assert_eq!(p.occupied(), Rank::R1.bb() | Rank::R2 | Rank::R7 | Rank::R8);
+        assert_eq!(p.occupied_by(Player::White), Rank::R1.bb() | Rank::R2);
+        assert_eq!(p.occupied_by(Player::Black), Rank::R7.bb() | Rank::R8);
+    }
+
+    #[test]
+    fn large_full_move_number() {
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/4K3", "b", "-", "-", "12", "40000");
+        assert_eq!(p.full_move_number(), 40000);
+        assert_eq!(p.turn(), crate::prelude::Player::Black);
+        assert_eq!(p.ply(), 79999);
+        let p = p.playmove("e8d8").unwrap().unwrap();
+        assert_eq!(p.full_move_number(), 40001);
+        assert_eq!(Position::startingpos().full_move_number(), 1);
+    }
+
+    #[test]
+    fn black_to_move_ply() {
+        use crate::prelude::*;
+
+        for (fen, ply) in [
+            (
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1",
+                1,
+            ),
+            ("4k3/8/8/8/8/8/8/4K3 b - - 3 2", 3),
+            ("4k3/8/8/8/8/8/8/4K3 b - - 0 17", 33),
+            ("4k3/8/8/8/8/8/8/4K3 b - - 50 100", 199),
+        ] {
+            let mut p = Position::extract_fen(&mut fen.split_whitespace()).unwrap();
+            assert_eq!(p.turn(), Player::Black, "{fen}");
+            assert_eq!(p.ply(), ply, "{fen}");
+            assert_eq!(p.to_fen(), fen);
+            // black's move ends the full move, and is taken back
+            let m = p.legal_moves()[0];
+            let undo = p.stack(&m, m.promotion());
+            assert_eq!(p.turn(), Player::White);
+            assert_eq!(p.ply(), ply + 1);
+            let full: u32 = fen.rsplit(' ').next().unwrap().parse().unwrap();
+            assert_eq!(p.full_move_number(), full + 1);
+            p.unstack(&m, undo);
+            assert_eq!(p.to_fen(), fen);
+        }
+        // a full move number of 0 is read as the first one
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/4K3", "b", "-", "-", "0", "0");
+        assert_eq!(p.ply(), 1);
+        assert_eq!(p.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn fifty_move_counter() {
+        let p = Position::from_fen("4k3/3p4/8/8/8/8/8/R3K3", "w", "-", "-", "7", "10");
+        let p = p.playmove("a1a2").unwrap().unwrap();
+        assert_eq!(p.fifty_mv, 8);
+        // pawn moves and captures reset it
+        assert_eq!(p.playmove("d7d5").unwrap().unwrap().fifty_mv, 0);
+        let p = p.playmove("e8e7").unwrap().unwrap();
+        let p = p.playmove("a2d2").unwrap().unwrap();
+        let p = p.playmove("e7e6").unwrap().unwrap();
+        assert_eq!(p.fifty_mv, 11);
+        assert_eq!(p.playmove("d2d7").unwrap().unwrap().fifty_mv, 0);
+        assert_eq!(p.playmove("d2d6").unwrap().unwrap().fifty_mv, 12);
+
+        // outcomes explored by map_issues agree with the moves played
+        use crate::position::AugmentedPos;
+        let p = Position::from_fen("4k3/3p4/8/8/8/8/3R4/4K3", "b", "-", "-", "7", "10");
+        let counters = AugmentedPos::map_issues(
+            &p,
+            |q, m| vec![(format!("{m}"), q.fifty_mv)],
+            |mut a, mut b| {
+                a.append(&mut b);
+                a
+            },
+        );
+        for (m, fifty_mv) in counters.unwrap() {
+            assert_eq!(fifty_mv, p.playmove(&m).unwrap().unwrap().fifty_mv, "{m}");
+        }
+    }
+
     #[test]
     fn captures_en_passant() {
         let p = Position::from_fen("7k/8/8/8/1p6/8/P7/7K", "w", "-", "-", "0", "0");
@@ -637,6 +1795,446 @@ mod tests {
         //    "Failed promotion to queen"
         //); // king in check
     }
+
+    #[test]
+    fn endgame_setups() {
+        use crate::prelude::*;
+        use Square::*;
+
+        let fen = |board, turn| Position::from_fen(board, turn, "-", "-", "0", "1").to_fen();
+        let kpk = Position::kpk(e1, e8, e2, Player::White).unwrap();
+        assert_eq!(kpk.to_fen(), fen("4k3/8/8/8/8/8/4P3/4K3", "w"));
+        assert!(kpk == Position::from_fen("4k3/8/8/8/8/8/4P3/4K3", "w", "-", "-", "0", "1"));
+        let krk = Position::krk(g1, h8, a7, Player::Black).unwrap();
+        assert_eq!(krk.to_fen(), fen("7k/R7/8/8/8/8/8/6K1", "b"));
+        let kqk = Position::kqk(c6, a8, d5, Player::White).unwrap();
+        assert_eq!(kqk.to_fen(), fen("k7/8/2K5/3Q4/8/8/8/8", "w"));
+        let kbnk = Position::kbnk(d6, h8, c4, e5, Player::White).unwrap();
+        assert_eq!(kbnk.to_fen(), fen("7k/8/3K4/4N3/2B5/8/8/8", "w"));
+        assert!(!kbnk.is_insufficient_material());
+
+        let error = |p: Result<Position, SetupError>| p.unwrap_err();
+        assert_eq!(
+            error(Position::kpk(e1, e8, e1, Player::White)),
+            SetupError::SquareTaken(e1.bb())
+        );
+        assert_eq!(
+            error(Position::kpk(e1, e8, a8, Player::White)),
+            SetupError::PawnOnBackRank(a8.bb())
+        );
+        // black in check is fine with black to move only
+        assert!(Position::krk(e1, e8, e7, Player::Black).unwrap().is_check());
+        assert_eq!(
+            error(Position::krk(e1, e8, e7, Player::White)),
+            SetupError::OpponentInCheck
+        );
+        assert_eq!(
+            error(Position::krk(e4, e5, a1, Player::White)),
+            SetupError::OpponentInCheck
+        );
+        let lone_king = [(Player::White, Piece::King, e1)];
+        assert_eq!(
+            error(Position::from_pieces(&lone_king, Player::White)),
+            SetupError::KingCount(Player::Black)
+        );
+    }
+
+    #[test]
+    fn san_parsing() {
+        let fen = |board, turn| Position::from_fen(board, turn, "-", "-", "0", "1");
+        let uci =
+            |mut p: Position, san: &str| p.getmove_san(san).map(|m| m.map(|m| format!("{m}")));
+        let p = fen("1n2k3/8/5n2/8/8/8/8/4K3", "b");
+        assert_eq!(uci(p, "Nbd7").unwrap().unwrap(), "b8d7");
+        assert_eq!(uci(p, "Nfd7+!?").unwrap().unwrap(), "f6d7");
+        // ambiguous, then illegal
+        assert_eq!(uci(p, "Nd7"), Ok(None));
+        assert_eq!(uci(p, "Nd6"), Ok(None));
+        let p = fen("4k3/8/8/8/8/4R3/8/4R1K1", "w");
+        assert_eq!(uci(p, "R1e2").unwrap().unwrap(), "e1e2");
+        assert_eq!(uci(p, "R3e2").unwrap().unwrap(), "e3e2");
+        assert_eq!(uci(p, "Rxe8+").unwrap().unwrap(), "e3e8");
+
+        let p = fen("3r3k/4P3/8/8/8/8/8/K7", "w");
+        assert_eq!(uci(p, "e8=Q+").unwrap().unwrap(), "e7e8q");
+        assert_eq!(uci(p, "exd8=N").unwrap().unwrap(), "e7d8n");
+        assert_eq!(uci(p, "e8"), Ok(None));
+        let p = Position::from_fen("r3k3/8/8/8/8/8/8/4K3", "b", "q", "-", "0", "1");
+        assert_eq!(uci(p, "O-O-O").unwrap().unwrap(), "e8c8");
+        assert_eq!(uci(p, "O-O"), Ok(None));
+
+        for malformed in [
+            "", "Nz9", "e9", "Nbbd7", "exd8=K", "Pe4", "O-O-O-O", "e4x", "Né4",
+        ] {
+            assert_eq!(
+                uci(p, malformed),
+                Err(super::MoveError::Notation(malformed.to_string())),
+                "{malformed}"
+            );
+        }
+
+        // SAN output parsed back, along a game
+        let mut p = Position::startingpos();
+        let game = [
+            "e2e4", "c7c5", "g1f3", "d7d6", "d2d4", "c5d4", "f3d4", "g8f6", "b1c3",
+        ];
+        for m in game {
+            let san = p.getmove(m).unwrap().unwrap().to_san(&p);
+            assert_eq!(uci(p, &san).unwrap().unwrap(), m);
+            p = p.playmove(m).unwrap().unwrap();
+        }
+    }
+
+    #[test]
+    fn underpromotions() {
+        use crate::prelude::*;
+
+        let p = Position::from_fen("7k/4P3/8/8/8/8/8/K7", "w", "-", "-", "0", "1");
+        for (suffix, piece) in [
+            ('q', Piece::Queen),
+            ('r', Piece::Rook),
+            ('b', Piece::Bishop),
+            ('n', Piece::Knight),
+        ] {
+            let uci = format!("e7e8{suffix}");
+            let m = p.clone().getmove(&uci).unwrap().expect(&uci);
+            assert_eq!(format!("{m}"), uci);
+            let after = p.playmove(&uci).unwrap().expect(&uci);
+            assert_eq!(after.pos.get((Player::White, Square::e8.bb())), Some(piece));
+        }
+        // each outcome counted by perft is listed with its own suffix
+        let (noisy, _) = p.staged_moves();
+        let mut listed: Vec<String> = noisy.iter().map(|m| format!("{m}")).collect();
+        listed.sort();
+        assert_eq!(listed, ["e7e8b", "e7e8n", "e7e8q", "e7e8r"]);
+        assert!(p.playmove("e7e8").unwrap().is_none());
+    }
+
+    #[test]
+    fn stack_matches_copy() {
+        use crate::position::AugmentedPos;
+
+        // the en passant square is reached by playing the double push
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                "w",
+                "KQkq",
+                None,
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R",
+                "w",
+                "KQkq",
+                None,
+            ),
+            (
+                "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR",
+                "b",
+                "KQkq",
+                Some("f7f5"),
+            ),
+            ("r3k3/1P6/8/8/8/8/6p1/4K2R", "b", "Kq", None),
+        ];
+        for (board, turn, castles, push) in cases {
+            let mut p = Position::from_fen(board, turn, castles, "-", "0", "1");
+            if let Some(m) = push {
+                p = p.playmove(m).unwrap().unwrap();
+            }
+            let root = p;
+            let copied = AugmentedPos::map_issues(
+                &p,
+                |c, _| vec![*c],
+                |mut a, mut b| {
+                    a.append(&mut b);
+                    a
+                },
+            );
+            let stacked = AugmentedPos::map_issues_mut(
+                &mut p,
+                |c, _| vec![*c],
+                |mut a, mut b| {
+                    a.append(&mut b);
+                    a
+                },
+            );
+            assert_eq!(p, root, "position not restored after unstack ({board})");
+            assert_eq!(copied, stacked, "in place outcomes differ ({board})");
+        }
+    }
+
+    #[test]
+    fn make_paths_agree() {
+        use crate::position::{AugmentedPos, Move};
+
+        // (move, FEN, hash) of every outcome, played on a copy or stacked on the position itself
+        type Outcomes = Vec<(String, String, usize)>;
+        fn outcome(c: &Position, m: &Move) -> Outcomes {
+            vec![(format!("{m}"), c.to_fen(), c.hash())]
+        }
+        fn outcomes(p: &Position) -> (Outcomes, Outcomes) {
+            let append = |mut a: Outcomes, mut b: Outcomes| {
+                a.append(&mut b);
+                a
+            };
+            let copied = AugmentedPos::map_issues(p, outcome, append);
+            let stacked =
+                AugmentedPos::map_issues_mut(&mut p.clone(), |c, m| outcome(c, m), append);
+            (copied.unwrap_or_default(), stacked.unwrap_or_default())
+        }
+        fn compare(p: &Position, depth: usize) {
+            let (copied, stacked) = outcomes(p);
+            assert_eq!(
+                copied.len(),
+                stacked.len(),
+                "move count differs in {}",
+                p.to_fen()
+            );
+            for (c, s) in copied.iter().zip(&stacked) {
+                assert_eq!(c, s, "outcomes differ in {}", p.to_fen());
+            }
+            if depth > 1 {
+                for (m, _, _) in copied {
+                    compare(&p.playmove(&m).unwrap().unwrap(), depth - 1);
+                }
+            }
+        }
+
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            let p = Position::extract_fen(&mut fen.split_whitespace()).unwrap();
+            compare(&p, 2);
+        }
+    }
+
+    #[test]
+    fn castling_from_uci() {
+        use crate::position::castle::Castle;
+        use crate::prelude::*;
+
+        let mut p = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R", "w", "KQkq", "-", "0", "1");
+        match p.getmove("e1g1").unwrap() {
+            Some(Move::Castle(Castle::Short, Player::White, _)) => (),
+            m => panic!("e1g1 should be white short castle, got {m:?}"),
+        }
+
+        let short = p.playmove("e1g1").unwrap().unwrap();
+        assert_eq!(
+            short.pos[(Player::White, Piece::King)],
+            Square::g1.declass()
+        );
+        assert_eq!(
+            short.pos[(Player::White, Piece::Rook)],
+            Square::a1.declass() | Square::f1
+        );
+        assert!(!short.castles.fetch(Player::White, Castle::Short));
+        assert!(!short.castles.fetch(Player::White, Castle::Long));
+        assert!(short.castles.fetch(Player::Black, Castle::Short));
+
+        let long = short.playmove("e8c8").unwrap().unwrap();
+        assert_eq!(long.pos[(Player::Black, Piece::King)], Square::c8.declass());
+        assert_eq!(
+            long.pos[(Player::Black, Piece::Rook)],
+            Square::d8.declass() | Square::h8
+        );
+        assert!(!long.castles.fetch(Player::Black, Castle::Long));
+    }
+
+    #[test]
+    fn chess960_castling() {
+        use crate::position::castle::Castle;
+        use crate::prelude::*;
+
+        // kings on f1 and f8 between rooks on the b and g files, castles are written as the
+        // king taking its rook
+        let mut p = Position::from_fen(
+            "1r3kr1/1p3pp1/8/8/8/8/1P4P1/1R3KR1",
+            "w",
+            "GBgb",
+            "-",
+            "0",
+            "1",
+        );
+        assert_eq!(
+            p.to_fen(),
+            "1r3kr1/1p3pp1/8/8/8/8/1P4P1/1R3KR1 w GBgb - 0 1"
+        );
+        let short = p.getmove("f1g1").unwrap().unwrap();
+        assert!(matches!(
+            short,
+            Move::Castle(Castle::Short, Player::White, _)
+        ));
+        assert_eq!(format!("{short}"), "f1g1");
+
+        let short = p.playmove("f1g1").unwrap().unwrap();
+        assert_eq!(
+            short.pos[(Player::White, Piece::King)],
+            Square::g1.declass()
+        );
+        assert_eq!(
+            short.pos[(Player::White, Piece::Rook)],
+            Square::b1.declass() | Square::f1
+        );
+        assert_eq!(
+            short.to_fen(),
+            "1r3kr1/1p3pp1/8/8/8/8/1P4P1/1R3RK1 b gb - 1 1"
+        );
+
+        let long = short.playmove("f8b8").unwrap().unwrap();
+        assert_eq!(long.pos[(Player::Black, Piece::King)], Square::c8.declass());
+        assert_eq!(
+            long.pos[(Player::Black, Piece::Rook)],
+            Square::d8.declass() | Square::g8
+        );
+
+        // the b1 rook shields the king from the a1 queen until it castles
+        let p = Position::from_fen("7k/8/8/8/8/8/8/qRK5", "w", "B", "-", "0", "1");
+        assert!(
+            !p.legal_moves()
+                .iter()
+                .any(|m| matches!(m, Move::Castle(..)))
+        );
+    }
+
+    #[test]
+    fn legal_moves_for_piece() {
+        use crate::prelude::*;
+        let p = Position::startingpos();
+        assert_eq!(p.legal_moves_for(Piece::Knight).len(), 4);
+        assert_eq!(p.legal_moves_for(Piece::Pawn).len(), 16);
+        assert_eq!(p.legal_moves_for(Piece::Queen).len(), 0);
+        assert_eq!(p.legal_moves_for(Piece::King).len(), 0);
+
+        // castles are listed with the king moves
+        let p = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R", "w", "KQkq", "-", "0", "1");
+        assert_eq!(p.legal_moves_for(Piece::King).len(), 5 + 2);
+    }
+
+    #[test]
+    fn castling_rights_sanitized() {
+        use crate::position::castle::Castle;
+        use crate::prelude::*;
+
+        // white king on e2, black h8 rook missing
+        let p = Position::from_fen("r3k3/8/8/8/8/8/4K3/R6R", "w", "KQkq", "-", "0", "1");
+        assert!(!p.castles.fetch(Player::White, Castle::Short));
+        assert!(!p.castles.fetch(Player::White, Castle::Long));
+        assert!(!p.castles.fetch(Player::Black, Castle::Short));
+        assert!(p.castles.fetch(Player::Black, Castle::Long));
+    }
+
+    #[test]
+    fn in_check_after_pinned_move() {
+        use crate::position::movegen::SimplifiedMove;
+        use crate::prelude::*;
+
+        let mv = |piece, src: Square, dest: Square| {
+            Move::Normal(SimplifiedMove {
+                piece,
+                src: src.bb().into(),
+                dest: dest.bb().into(),
+                hint_legal: false,
+                promotion: None,
+            })
+        };
+        // e2 bishop pinned by the e7 rook
+        let p = Position::from_fen("4k3/4r3/8/8/8/8/4B3/4K3", "w", "-", "-", "0", "1");
+        assert!(p.in_check_after(&mv(Piece::Bishop, Square::e2, Square::d3)));
+        assert!(!p.in_check_after(&mv(Piece::King, Square::e1, Square::d1)));
+    }
+
+    #[test]
+    fn quiet_positions() {
+        assert!(Position::startingpos().is_quiet());
+        // hanging black queen, Re2+ also available
+        let p = Position::from_fen("4k3/8/8/3q4/8/8/3R4/4K3", "w", "-", "-", "0", "1");
+        assert!(!p.is_quiet());
+        assert_eq!(p.legal_captures().len(), 1);
+        // in check without any capture
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/r3K3", "w", "-", "-", "0", "1");
+        assert!(p.legal_captures().is_empty());
+        assert!(!p.is_quiet());
+    }
+
+    #[test]
+    fn staged_moves_buckets() {
+        let kiwipete = Position::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R",
+            "w",
+            "KQkq",
+            "-",
+            "0",
+            "1",
+        );
+        let (noisy, quiet) = kiwipete.staged_moves();
+        assert_eq!(kiwipete.legal_move_count(), 48);
+        assert_eq!(noisy.len(), 8);
+        assert_eq!(noisy.len() + quiet.len(), kiwipete.perft(1));
+        assert!(noisy.iter().all(|m| kiwipete.is_capture(m)));
+        assert!(
+            quiet
+                .iter()
+                .all(|m| !kiwipete.is_capture(m) && !kiwipete.is_promotion(m))
+        );
+
+        let promotion = Position::from_fen("7k/P7/8/8/8/8/8/7K", "w", "-", "-", "0", "1");
+        let (noisy, quiet) = promotion.staged_moves();
+        assert_eq!((noisy.len(), quiet.len()), (4, 3));
+        assert!(noisy.iter().all(|m| promotion.is_promotion(m)));
+    }
+
+    #[test]
+    fn promotion_capture_stack() {
+        use crate::prelude::*;
+
+        let mut p = Position::from_fen("3r3k/4P3/8/8/8/8/8/K7", "w", "-", "-", "0", "1");
+        let root = p;
+        let m = p.getmove("e7d8q").unwrap().unwrap();
+        assert!(p.is_capture(&m) && p.is_promotion(&m));
+        let copied = p.playmove("e7d8q").unwrap().unwrap();
+
+        let undo = p.stack(&m, m.promotion());
+        assert!(p == copied);
+        let d8 = Square::d8.bb();
+        assert_eq!(p.pos.get((Player::White, d8)), Some(Piece::Queen));
+        assert_eq!(p.pos.get((Player::Black, d8)), None);
+        assert!(p.pos[(Player::White, Piece::Pawn)] == SpecialBB::Empty.declass());
+        assert!(p.pos[(Player::Black, Piece::Rook)] == SpecialBB::Empty.declass());
+        p.unstack(&m, undo);
+        assert!(p == root);
+    }
+
+    #[test]
+    fn castling_without_rights() {
+        let mut p = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R", "w", "-", "-", "0", "1");
+        assert!(p.getmove("e1g1").unwrap().is_none());
+        assert!(p.playmove("e1g1").unwrap().is_none());
+        assert!(p.playmove("e1c1").unwrap().is_none());
+    }
+}
+
+// pawns on both sides able to promote by pushing or capturing
+#[test]
+fn promotions() {
+    perft_test_batch(
+        "promotions",
+        &[1, 24, 496, 9483, 182838],
+        "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N",
+        "b",
+        "-",
+        "-",
+        "0",
+        "1",
+    );
+    let mut p = Position::from_fen("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N", "b", "-", "-", "0", "1");
+    assert_eq!(p.perft_in_place(3), 9483);
 }
 
 #[test]
@@ -695,6 +2293,40 @@ fn random_opening() {
     )
 }
 
+// reference counts of Chess960 positions, with rooks given by file in the castling field
+#[test]
+fn chess960_perft() {
+    for (depths, fen, castles) in [
+        (
+            [1, 21, 528, 12189, 326672],
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR",
+            "HFhf",
+        ),
+        (
+            [1, 21, 807, 18002, 667366],
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR",
+            "HEhe",
+        ),
+        (
+            [1, 20, 479, 10471, 273318],
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB",
+            "GE",
+        ),
+        (
+            [1, 29, 502, 14569, 287739],
+            "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN",
+            "FBfb",
+        ),
+        (
+            [1, 24, 600, 15347, 408207],
+            "rqbbknr1/1ppp2pp/p5n1/4pp2/P7/1PP5/1Q1PPPPP/R1BBKNRN",
+            "GAga",
+        ),
+    ] {
+        perft_test_batch("Chess960", &depths, fen, "w", castles, "-", "0", "9");
+    }
+}
+
 #[test]
 fn perft_startpos_extensive() {
     perft_test_batch(
@@ -708,6 +2340,34 @@ fn perft_startpos_extensive() {
         "1",
     );
 }
+#[test]
+fn legal_moves_match_perft() {
+    for (fen, turn, castles) in [
+        ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR", "w", "KQkq"),
+        ("k7/8/8/8/8/8/P7/7K", "w", "-"),
+        ("k7/8/8/8/8/8/N7/7K", "w", "-"),
+        ("k7/8/8/8/8/8/B7/7K", "w", "-"),
+        (
+            "r3k2r/ppp2ppp/2n1bn2/2b1p3/4P3/2N2N2/PPPP1PPP/R1B1KB1R",
+            "w",
+            "KQkq",
+        ),
+    ] {
+        let p = Position::from_fen(fen, turn, castles, "-", "0", "1");
+        assert_eq!(p.legal_moves().len(), p.perft(1), "{fen}");
+        // and one ply deeper, from every resulting position
+        for m in p.legal_moves() {
+            let next = p.playmove(&format!("{m}")).unwrap().unwrap();
+            assert_eq!(next.legal_moves().len(), next.perft(1), "{fen} {m}");
+        }
+    }
+    // the counts of the perft batches above
+    assert_eq!(Position::startingpos().legal_moves().len(), 20);
+    let fen = "r3k2r/ppp2ppp/2n1bn2/2b1p3/4P3/2N2N2/PPPP1PPP/R1B1KB1R";
+    let p = Position::from_fen(fen, "w", "KQkq", "-", "0", "1");
+    assert_eq!(p.legal_moves().len(), 30);
+}
+
 #[cfg(test)]
 fn perft_test_batch(
     name: &str,
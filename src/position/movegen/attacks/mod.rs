@@ -6,12 +6,16 @@
 //! The module exposes a single interface through feature flags:
 //! - With `static_attacks` enabled, uses the static lookup tables for bishops/rooks/queens
 //! - Without `static_attacks`, uses dynamic generation for all pieces
+//!
+//! Both are also available whatever the features through `AttackGen`, chosen at runtime.
 
 mod dyn_attacks;
 
-#[cfg(feature = "static_attacks")]
+#[cfg_attr(not(feature = "static_attacks"), allow(dead_code))]
 mod static_attacks;
 
+pub use static_attacks::{Lookup, STATIC_ATTACKS};
+
 #[cfg(feature = "static_attacks")]
 pub use static_attacks::{generate_bishops, generate_queens, generate_rooks};
 
@@ -19,6 +23,60 @@ pub use static_attacks::{generate_bishops, generate_queens, generate_rooks};
 pub use dyn_attacks::{generate_bishops, generate_queens, generate_rooks};
 
 pub use dyn_attacks::{generate_king, generate_knights, generate_pawns};
+
+use crate::prelude::*;
+
+// slider attack generation picked at runtime, to compare both in one binary
+#[derive(Clone, Copy, Debug)]
+pub enum AttackGen {
+    Dynamic,
+    // tables are built on first use
+    Static(&'static Lookup),
+}
+
+impl AttackGen {
+    pub fn static_tables() -> Self {
+        Self::Static(&STATIC_ATTACKS)
+    }
+
+    pub fn generate_bishops(
+        self,
+        p: Bitboard<GenericBB>,
+        blockers: Bitboard<GenericBB>,
+    ) -> Bitboard<GenericBB> {
+        match self {
+            Self::Dynamic => dyn_attacks::generate_bishops(p, blockers),
+            Self::Static(l) => l.generate_bishops(p, blockers),
+        }
+    }
+
+    pub fn generate_rooks(
+        self,
+        p: Bitboard<GenericBB>,
+        blockers: Bitboard<GenericBB>,
+    ) -> Bitboard<GenericBB> {
+        match self {
+            Self::Dynamic => dyn_attacks::generate_rooks(p, blockers),
+            Self::Static(l) => l.generate_rooks(p, blockers),
+        }
+    }
+
+    pub fn generate_queens(
+        self,
+        p: Bitboard<GenericBB>,
+        blockers: Bitboard<GenericBB>,
+    ) -> Bitboard<GenericBB> {
+        self.generate_bishops(p, blockers) | self.generate_rooks(p, blockers)
+    }
+
+    pub fn generate_knights(self, p: Bitboard<GenericBB>) -> Bitboard<GenericBB> {
+        match self {
+            Self::Dynamic => dyn_attacks::generate_knights(p),
+            Self::Static(l) => l.generate_knights(p),
+        }
+    }
+}
+
 mod tests {
 
     #[allow(unused)]
@@ -70,7 +128,13 @@ mod tests {
             | Bitboard(Square::h8)
             | Bitboard(Square::c5)
             | Bitboard(Square::e3);
-        assert_eq!(generate_bishops(bishop.declass(), blockers), expected);
+        let attacks = generate_bishops(bishop.declass(), blockers);
+        assert!(
+            attacks == expected,
+            "\n{}\nexpected\n{}",
+            attacks.pretty(),
+            expected.pretty()
+        );
     }
 
     #[test]
@@ -100,6 +164,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn runtime_generators_agree() {
+        use movegen::attacks::AttackGen;
+
+        let (dynamic, tables) = (AttackGen::Dynamic, AttackGen::static_tables());
+        // xorshift, sparse and dense blockers
+        let mut x: u64 = 0x9E3779B97F4A7C15;
+        for sq in SpecialBB::Full.declass() {
+            for _ in 0..8 {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                for blockers in [x & x.rotate_left(7), x] {
+                    let (p, b) = (sq.declass(), Bitboard(GenericBB(blockers)));
+                    assert_eq!(
+                        dynamic.generate_bishops(p, b),
+                        tables.generate_bishops(p, b)
+                    );
+                    assert_eq!(dynamic.generate_rooks(p, b), tables.generate_rooks(p, b));
+                    assert_eq!(dynamic.generate_queens(p, b), tables.generate_queens(p, b));
+                }
+            }
+            assert_eq!(
+                dynamic.generate_knights(sq.declass()),
+                tables.generate_knights(sq.declass())
+            );
+        }
+    }
+
     #[test]
     fn test_pawn_attacks() {
         let pawn = Bitboard(Square::d4);
@@ -62,52 +62,84 @@ impl Lookup {
     }
 }
 
+impl Lookup {
+    pub fn generate_bishops(
+        &self,
+        p: Bitboard<GenericBB>,
+        blockers: Bitboard<GenericBB>,
+    ) -> Bitboard<GenericBB> {
+        let mut dests = SpecialBB::Empty.declass(); // TODO: remove p without bizarre behaviour
+        for s in p {
+            dests |= self.at_bishop[(s, blockers)] & !s
+        }
+        debug_assert_eq!(
+            dests,
+            dyn_attacks::generate_bishops(p, blockers),
+            "Bishop in {} with {} as blockers gave different outcomes : {}, {}",
+            p,
+            blockers,
+            dests,
+            dyn_attacks::generate_bishops(p, blockers)
+        );
+        dests
+    }
+
+    pub fn generate_rooks(
+        &self,
+        p: Bitboard<GenericBB>,
+        blockers: Bitboard<GenericBB>,
+    ) -> Bitboard<GenericBB> {
+        let mut dests = SpecialBB::Empty.declass();
+        for s in p {
+            dests |= self.at_rook[(s, blockers)] ^ s
+        }
+
+        debug_assert_eq!(
+            dests,
+            dyn_attacks::generate_rooks(p, blockers),
+            "Rook in {} with {} as blockers gave different outcomes : {}, {}",
+            p,
+            (blockers),
+            dests,
+            dyn_attacks::generate_rooks(p, blockers)
+        );
+        dests
+    }
+
+    pub fn generate_queens(
+        &self,
+        p: Bitboard<GenericBB>,
+        blockers: Bitboard<GenericBB>,
+    ) -> Bitboard<GenericBB> {
+        self.generate_bishops(p, blockers) | self.generate_rooks(p, blockers)
+    }
+
+    pub fn generate_knights(&self, p: Bitboard<GenericBB>) -> Bitboard<GenericBB> {
+        p.into_iter().fold(SpecialBB::Empty.declass(), |dests, s| {
+            dests | self.at_knights[s.to_index() as usize]
+        })
+    }
+}
+
 pub fn generate_bishops(
     p: Bitboard<GenericBB>,
     blockers: Bitboard<GenericBB>,
 ) -> Bitboard<GenericBB> {
-    let mut dests = SpecialBB::Empty.declass(); // TODO: remove p without bizarre behaviour
-    for s in p {
-        dests = dests | (STATIC_ATTACKS.at_bishop[(s, blockers)] & !s)
-    }
-    debug_assert_eq!(
-        dests,
-        dyn_attacks::generate_bishops(p, blockers),
-        "Bishop in {} with {} as blockers gave different outcomes : {}, {}",
-        p,
-        blockers,
-        dests,
-        dyn_attacks::generate_bishops(p, blockers)
-    );
-    dests
+    STATIC_ATTACKS.generate_bishops(p, blockers)
 }
 
 pub fn generate_rooks(
     p: Bitboard<GenericBB>,
     blockers: Bitboard<GenericBB>,
 ) -> Bitboard<GenericBB> {
-    let mut dests = SpecialBB::Empty.declass();
-    for s in p {
-        dests = dests | (STATIC_ATTACKS.at_rook[(s, blockers)] ^ s)
-    }
-
-    debug_assert_eq!(
-        dests,
-        dyn_attacks::generate_rooks(p, blockers),
-        "Rook in {} with {} as blockers gave different outcomes : {}, {}",
-        p,
-        (blockers),
-        dests,
-        dyn_attacks::generate_rooks(p, blockers)
-    );
-    dests
+    STATIC_ATTACKS.generate_rooks(p, blockers)
 }
 
 pub fn generate_queens(
     p: Bitboard<GenericBB>,
     blockers: Bitboard<GenericBB>,
 ) -> Bitboard<GenericBB> {
-    generate_bishops(p, blockers) | generate_rooks(p, blockers)
+    STATIC_ATTACKS.generate_queens(p, blockers)
 }
 
 #[derive(Clone, Debug)]
@@ -149,7 +181,7 @@ impl<const N: usize> AttackTablePart<N> {
             if Instant::now() > last_print + Duration::from_secs(10) {
                 last_print = Instant::now();
                 let x = last_print - start_time;
-                println!(
+                dprintln!(
                     "Still searching for sq {} (blockers = {}) (time spent {}ms)",
                     sq,
                     blockers,
@@ -177,7 +209,7 @@ impl<const N: usize> AttackTablePart<N> {
             }
             if found {
                 if verbose || tries > 1 {
-                    println!("Found key for sq {} (bl {}) - {}", sq, blockers, key);
+                    dprintln!("Found key for sq {} (bl {}) - {}", sq, blockers, key);
                 }
                 return Self {
                     key,
@@ -231,7 +263,7 @@ impl<const N: usize> AttackTable<N> {
         print_new_keys: bool,
     ) {
         if print_new_keys {
-            println!("Searching for new keys...");
+            dprintln!("Searching for new keys...");
         }
 
         let mut keys = [0; 64];
@@ -246,7 +278,7 @@ impl<const N: usize> AttackTable<N> {
         }
 
         if print_new_keys {
-            println!("Keys selected : {:?}", keys);
+            dprintln!("Keys selected : {:?}", keys);
         }
         self.init = Some(())
     }
@@ -10,6 +10,11 @@ pub fn generate_king_dests(src: Bitboard<Square>, meta: &AugmentedPos) -> Bitboa
     let player = meta.turn;
     let king = src; // meta.p.pos[player].pieces[PieceN::King as usize];
 
+    // in check, a slider also attacks the squares behind the king
+    if meta.attacked[player.other() as usize] & king != SpecialBB::Empty.declass() {
+        return meta.p.king_safe_squares(player);
+    }
+
     // squares that arent attacked by opponent, and not occupied by my own pieces
     log::trace!(
         "King gen: Other player attacks: {}, occupied {}",
@@ -18,7 +23,6 @@ pub fn generate_king_dests(src: Bitboard<Square>, meta: &AugmentedPos) -> Bitboa
     );
     let free_sq_for_king =
         (!meta.attacked[player.other() as usize]) & (!meta.p.pos.occupied(player));
-
     let r = attacks::generate_king(king) & free_sq_for_king;
     debug!(
         "King dests for this turn {} -> {} \n (details) -- {} & {} ",
@@ -6,8 +6,9 @@ use dests::{generate_king_dests, pawn_move_up_nocap};
 use log::warn;
 
 use super::Player;
-use super::castle::{CASTLES_KEEP_UNCHANGED, Castle, CastleData};
+use super::castle::{CASTLES_KEEP_UNCHANGED, Castle, CastleData, CastleFiles};
 use crate::bitboard::Bitboard;
+use crate::localvec::FastVec;
 
 use super::Position;
 
@@ -24,44 +25,160 @@ pub trait TransitionSet<T> {}
 #[derive(Clone, Copy, Debug)]
 pub enum Move {
     Normal(SimplifiedMove),
-    Castle(Castle, Player),
+    Castle(Castle, Player, CastleFiles),
 }
 
 impl Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Move::Normal(x) => write!(f, "{}", x),
-            Move::Castle(c, p) => match c {
-                Castle::Short => match p {
-                    Player::Black => write!(f, "e8g8"),
-                    Player::White => write!(f, "e1g1"),
-                },
-                Castle::Long => match p {
-                    Player::Black => write!(f, "e8c8"),
-                    Player::White => write!(f, "e1c1"),
-                },
-            },
+            Move::Castle(_, _, _) => {
+                let (src, dest) = self.from_to();
+                let (src, dest) = (Bitboard::from_index(src), Bitboard::from_index(dest));
+                write!(f, "{src}{dest}")
+            }
         }
     }
 }
 
+impl Move {
+    // origin and destination square indices
+    // castles are written as the king's move in standard chess (e1g1), and as the king taking
+    // its own rook in Chess960 (f1h1), the king's destination being ambiguous there
+    pub fn from_to(&self) -> (u8, u8) {
+        match self {
+            Move::Normal(x) => (x.src.0 as u8, x.dest.0 as u8),
+            Move::Castle(c, p, files) => {
+                let rank = 56 * (*p as u8);
+                match files.is_standard(*c) {
+                    true => (rank + files.king, rank + c.king_dest_file()),
+                    false => (rank + files.king, rank + files.rook),
+                }
+            }
+        }
+    }
+
+    // the same move, promoting to the given piece
+    pub fn with_promotion(self, promotion: Option<Piece>) -> Move {
+        match self {
+            Move::Normal(x) => Move::Normal(SimplifiedMove { promotion, ..x }),
+            castle => castle,
+        }
+    }
+
+    pub fn promotion(&self) -> Option<Piece> {
+        match self {
+            Move::Normal(x) => x.promotion,
+            Move::Castle(_, _, _) => None,
+        }
+    }
+
+    // human readable form for traces, e.g. "White Knight g1→f3 (capture: none)"
+    pub fn describe(&self, pos: &Position) -> String {
+        let turn = pos.turn();
+        let (src, dest) = self.from_to();
+        let (src, dest) = (Bitboard::from_index(src), Bitboard::from_index(dest));
+        match self {
+            Move::Normal(x) => {
+                let captured = match pos.pos().get((turn.other(), dest)) {
+                    Some(p) => format!("{p:?}"),
+                    None if pos.is_capture(self) => "Pawn (en passant)".to_string(),
+                    None => "none".to_string(),
+                };
+                format!("{turn:?} {:?} {src}→{dest} (capture: {captured})", x.piece)
+            }
+            Move::Castle(c, p, _) => format!("{p:?} King {src}→{dest} (castle: {c:?})"),
+        }
+    }
+
+    // standard algebraic notation, e.g. "Nf3", "exd5", "O-O", "e8=Q+", "Raxd1"
+    // the move has to be legal in pos
+    pub fn to_san(&self, pos: &Position) -> String {
+        let mut san = match self {
+            Move::Castle(Castle::Short, _, _) => "O-O".to_string(),
+            Move::Castle(Castle::Long, _, _) => "O-O-O".to_string(),
+            Move::Normal(x) => {
+                let (src, dest) = (format!("{}", x.src), format!("{}", x.dest));
+                let capture = match pos.is_capture(self) {
+                    true => "x",
+                    false => "",
+                };
+                let promotion = match x.promotion {
+                    Some(p) => format!("={}", ['P', 'N', 'B', 'R', 'Q', 'K'][p as usize]),
+                    None => String::new(),
+                };
+                match x.piece {
+                    // the file of a capturing pawn is all it takes to tell it apart
+                    Piece::Pawn => match capture {
+                        "x" => format!("{}x{dest}{promotion}", &src[..1]),
+                        _ => format!("{dest}{promotion}"),
+                    },
+                    piece => {
+                        let letter = ['P', 'N', 'B', 'R', 'Q', 'K'][piece as usize];
+                        let from = san_disambiguation(pos, x);
+                        format!("{letter}{from}{capture}{dest}")
+                    }
+                }
+            }
+        };
+        let mut after = *pos;
+        after.stack(self, self.promotion());
+        if after.is_check() {
+            san.push(match after.has_legal_moves() {
+                true => '+',
+                false => '#',
+            });
+        }
+        san
+    }
+}
+
+// origin square of a piece move, as little of it as needed to tell it apart from the other
+// pieces of the same type reaching the same square
+fn san_disambiguation(pos: &Position, x: &SimplifiedMove) -> String {
+    let src: Bitboard<Square> = x.src.into();
+    let rivals: Vec<Bitboard<Square>> = pos
+        .legal_moves_for(x.piece)
+        .iter()
+        .filter_map(|m| match m {
+            Move::Normal(y) if y.dest.0 == x.dest.0 && y.src.0 != x.src.0 => Some(y.src.into()),
+            _ => None,
+        })
+        .collect();
+    let name = format!("{src}");
+    if rivals.is_empty() {
+        String::new()
+    } else if rivals.iter().all(|r| r.file() != src.file()) {
+        name[..1].to_string()
+    } else if rivals.iter().all(|r| r.rank() != src.rank()) {
+        name[1..].to_string()
+    } else {
+        name
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct SimplifiedMove {
     pub src: Bitboard<PackedSquare>,
     pub dest: Bitboard<PackedSquare>,
     pub piece: Piece,
     pub hint_legal: bool,
+    // piece chosen by a pawn reaching the last rank, set once the outcome is explored
+    pub promotion: Option<Piece>,
 }
 impl Display for SimplifiedMove {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.piece == Piece::Pawn
             && self.dest.declass() & (Rank::R1.bb() | Rank::R8) != SpecialBB::Empty.declass()
         {
-            warn!(
-                "Promotions are not supported yet ({} -> {}), defaulting to queen.",
-                self.src, self.dest
-            );
-            let c: char = ['P', 'N', 'B', 'R', 'Q', 'K'][Piece::Queen as usize];
+            let promotion = self.promotion.unwrap_or_else(|| {
+                warn!(
+                    "Promotion piece not chosen ({} -> {}), defaulting to queen.",
+                    self.src, self.dest
+                );
+                Piece::Queen
+            });
+            let c: char = ['p', 'n', 'b', 'r', 'q', 'k'][promotion as usize];
             write!(f, "{}{}{}", self.src, self.dest, c)?;
         } else {
             write!(f, "{}{}", self.src, self.dest)?;
@@ -268,43 +385,44 @@ pub fn generate_castle_data(
         // reset castles for king/rook moves
         Piece::King => cd.copy_selection_player(meta.player(), &meta.p.castles),
         Piece::Rook => {
-            if src.declass() == (File::A.declass() & meta.player().backrank()) {
-                cd.copy_selection_precise(meta.player(), Castle::Long, &meta.p.castles)
-            } else if src.declass() == (File::H.declass() & meta.player().backrank()) {
-                cd.copy_selection_precise(meta.player(), Castle::Short, &meta.p.castles)
+            for c in [Castle::Short, Castle::Long] {
+                if is_castle_rook(meta.p.castles, meta.player(), c, src) {
+                    cd.copy_selection_precise(meta.player(), c, &meta.p.castles)
+                }
             }
         }
         _ => (),
     }
     // capture opponent rook
-    if dest.declass() == (File::A.declass() & meta.opponent().backrank()) {
-        cd.copy_selection_precise(meta.opponent(), Castle::Long, &meta.p.castles)
-    } else if dest.declass() == (File::H.declass() & meta.opponent().backrank()) {
-        cd.copy_selection_precise(meta.opponent(), Castle::Short, &meta.p.castles)
+    for c in [Castle::Short, Castle::Long] {
+        if is_castle_rook(meta.p.castles, meta.opponent(), c, dest) {
+            cd.copy_selection_precise(meta.opponent(), c, &meta.p.castles)
+        }
     }
 
     cd
 }
 
+// is sq the starting square of the rook of this castle
+fn is_castle_rook(cda: CastleData, p: Player, c: Castle, sq: &Bitboard<Square>) -> bool {
+    sq.declass() & p.backrank() != SpecialBB::Empty.declass()
+        && sq.to_index() % 8 == cda.rook_file(p, c)
+}
+
 fn iter_castle_moves<R>(cda: CastleData, m: &AugmentedPos) -> impl Iterator<Item = Move> {
     let player = m.player();
-    let blockers = m.p.pos.occupied(player) | m.p.pos.occupied(player.other());
+    let blockers = m.p.occupied();
     let attacks = m.attacked[player.other() as usize];
-    let x = [Castle::Short, Castle::Long]
-        .iter()
+    let king = m.p.pos[(player, Piece::King)].into_iter().next();
+    [Castle::Short, Castle::Long]
+        .into_iter()
+        .filter(move |c| cda.fetch(player, *c))
         .filter_map(move |c| {
-            if cda.fetch(player, *c) {
-                Some(*c)
-            } else {
-                None
-            }
+            let files = cda.files(player, c, king?);
+            let free = attacks & files.king_path(c, player) == SpecialBB::Empty.declass()
+                && blockers & files.path(c, player) == SpecialBB::Empty.declass();
+            free.then_some(Move::Castle(c, player, files))
         })
-        .filter(move |c| {
-            attacks & c.files() & player.backrank() == SpecialBB::Empty.declass()
-                && blockers & c.free_files() & player.backrank() == SpecialBB::Empty.declass()
-        });
-    let r = x.map(move |c| Move::Castle(c, player));
-    r
 }
 
 // -- prefilter legal, put pesudo legal remain
@@ -339,22 +457,48 @@ fn filter_pseudo_legal(p: &AugmentedPos, m: Move) -> Option<Move> {
     }
 }
 
+const PIECES: [Piece; 6] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+// pieces a move can promote to, in the order simplified_move_outcomes explores them
+pub(super) fn promotion_choices(m: &Move, turn: Player) -> &'static [Option<Piece>] {
+    match m {
+        Move::Normal(ch)
+            if ch.piece == Piece::Pawn
+                && ch.dest.declass() & turn.other().backrank() != SpecialBB::Empty.declass() =>
+        {
+            &[
+                Some(Piece::Queen),
+                Some(Piece::Bishop),
+                Some(Piece::Rook),
+                Some(Piece::Knight),
+            ]
+        }
+        _ => &[None],
+    }
+}
+
 // structure containing a
 #[derive(Debug)]
 pub struct AugmentedPos<'a> {
     p: &'a Position, // mutable to allow to simulate pseudo legal moves, but will always return it unchanged
     turn: Player,
     attacked: [Bitboard<GenericBB>; 2],
+    // squares of the lines between the king and the enemy sliders that would attack it without
+    // its own pieces in the way: the king, the potentially pinned pieces and the ray squares
+    // moves touching them are not hinted legal, hence checked after being played
     pinned: Bitboard<GenericBB>,
 }
 
 impl<'a> AugmentedPos<'a> {
-    pub fn map_issues<R>(
-        p: &Position,
-        task: impl Fn(&Position, &Move) -> R,
-        reduction: impl Fn(R, R) -> R,
-    ) -> Option<R> {
-        let turn = Player::from_usize((p.half_move_count % 2).into());
+    fn new(p: &'a Position) -> Self {
+        let turn = Player::from_usize((p.half_move_count % 2) as usize);
         let mut a = AugmentedPos {
             p,
             attacked: [SpecialBB::Empty.declass(), SpecialBB::Empty.declass()],
@@ -362,13 +506,83 @@ impl<'a> AugmentedPos<'a> {
             turn,
         };
         a.compute_pinned();
-
-        let a = a.gen_moves_map(task, &reduction);
+        a.attacked[turn.other() as usize] = p.pos.generate_attacks(turn.other());
+        a.attacked[turn as usize] = p.pos.generate_attacks(turn);
         a
     }
 
+    pub fn map_issues<R>(
+        p: &Position,
+        task: impl Fn(&Position, &Move) -> R,
+        reduction: impl Fn(R, R) -> R,
+    ) -> Option<R> {
+        AugmentedPos::new(p).gen_moves_map(&PIECES, task, &reduction)
+    }
+
+    // same as map_issues, restricted to the moves of a single piece type (castles are king moves)
+    pub fn map_piece_issues<R>(
+        p: &Position,
+        piece: Piece,
+        task: impl Fn(&Position, &Move) -> R,
+        reduction: impl Fn(R, R) -> R,
+    ) -> Option<R> {
+        AugmentedPos::new(p).gen_moves_map(&[piece], task, &reduction)
+    }
+
+    // same as map_issues, but plays every move on p itself (stack/unstack) instead of a copy
+    // p is handed back unchanged
+    pub fn map_issues_mut<R>(
+        p: &mut Position,
+        mut task: impl FnMut(&mut Position, &Move) -> R,
+        reduction: impl Fn(R, R) -> R,
+    ) -> Option<R> {
+        let turn = p.turn();
+        // moves are listed up front as the position is about to be modified
+        let mut moves = FastVec::<64, Move>::new();
+        AugmentedPos::new(p)
+            .pseudo_legal_moves(&PIECES)
+            .for_each(|m| moves.push(m));
+
+        let mut acc: Option<R> = None;
+        for m in moves.iter() {
+            for promotion in promotion_choices(m, turn) {
+                let undo = p.stack(m, *promotion);
+                let legal = match m {
+                    Move::Normal(ch) => {
+                        ch.hint_legal
+                            || p.pos.generate_attacks(turn.other()) & p.pos[(turn, Piece::King)]
+                                == SpecialBB::Empty.declass()
+                    }
+                    // castle moves are filtered before, but in Chess960 the rook may have been
+                    // shielding the king from a slider along the back rank
+                    Move::Castle(c, _, files) => {
+                        files.is_standard(*c)
+                            || p.pos.generate_attacks(turn.other()) & p.pos[(turn, Piece::King)]
+                                == SpecialBB::Empty.declass()
+                    }
+                };
+                if legal {
+                    let r = task(p, &m.with_promotion(*promotion));
+                    acc = Some(match acc {
+                        Some(x) => reduction(x, r),
+                        None => r,
+                    });
+                }
+                p.unstack(m, undo);
+            }
+        }
+        acc
+    }
+
+    // every legal move, promotions listed once per promotion piece
+    pub fn list_issues(p: &Position) -> Vec<Move> {
+        let mut moves = Vec::new();
+        AugmentedPos::map_issues_mut(&mut p.clone(), |_, m| moves.push(*m), |_, _| ());
+        moves
+    }
+
     pub fn check_legal(p: &Position) -> Result<(), ()> {
-        let turn = Player::from_usize((p.half_move_count % 2).into());
+        let turn = Player::from_usize((p.half_move_count % 2) as usize);
         let mut a = AugmentedPos {
             p,
             attacked: [SpecialBB::Empty.declass(), SpecialBB::Empty.declass()],
@@ -376,8 +590,6 @@ impl<'a> AugmentedPos<'a> {
             turn,
         };
 
-        let _blockers = a.p.pos.occupied(Player::White) | a.p.pos.occupied(Player::Black);
-
         a.attacked[a.turn as usize] = a.p.pos.generate_attacks(a.turn);
         if a.p.pos[(a.turn.other(), Piece::King)] & a.attacked[a.turn as usize]
             != SpecialBB::Empty.declass()
@@ -398,72 +610,62 @@ impl<'a> AugmentedPos<'a> {
         self.turn.other()
     }
 
-    fn gen_moves_map<R>(
-        &mut self,
-        task: impl Fn(&Position, &Move) -> R,
-        reduce: impl Fn(R, R) -> R,
-    ) -> Option<R> {
-        self.attacked[self.turn.other() as usize] = self.p.pos.generate_attacks(self.turn.other());
-        self.attacked[self.turn as usize] = self.p.pos.generate_attacks(self.turn);
-
-        let gen_dests = |piece: Piece, src: Bitboard<Square>| -> Bitboard<GenericBB> {
-            let free = !self.p.pos.occupied(self.turn);
-            let blockers = self.p.pos.occupied(self.turn.other()) | self.p.pos.occupied(self.turn);
-            free & match piece {
-                Piece::Pawn => {
-                    (attacks::generate_pawns(src.declass(), self.turn)
-                        & (self.p.en_passant | self.p.pos.occupied(self.turn.other())))
-                        | pawn_move_up_nocap(src, self.turn, blockers)
-                }
-                Piece::Knight => {
-                    attacks::generate_knights(src.declass()) & !self.p.pos.occupied(self.turn)
-                }
-                Piece::Bishop => attacks::generate_bishops(src.declass(), blockers),
-                Piece::Rook => attacks::generate_rooks(src.declass(), blockers),
-                Piece::Queen => attacks::generate_queens(src.declass(), blockers),
-                Piece::King => generate_king_dests(src, self),
+    fn gen_dests(&self, piece: Piece, src: Bitboard<Square>) -> Bitboard<GenericBB> {
+        let free = !self.p.pos.occupied(self.turn);
+        let blockers = self.p.occupied();
+        free & match piece {
+            Piece::Pawn => {
+                (attacks::generate_pawns(src.declass(), self.turn)
+                    & (self.p.en_passant | self.p.pos.occupied(self.turn.other())))
+                    | pawn_move_up_nocap(src, self.turn, blockers)
             }
-        };
+            Piece::Knight => {
+                attacks::generate_knights(src.declass()) & !self.p.pos.occupied(self.turn)
+            }
+            Piece::Bishop => attacks::generate_bishops(src.declass(), blockers),
+            Piece::Rook => attacks::generate_rooks(src.declass(), blockers),
+            Piece::Queen => attacks::generate_queens(src.declass(), blockers),
+            Piece::King => generate_king_dests(src, self),
+        }
+    }
 
-        use enum_iterator::all;
-        let a = all::<Piece>()
-            .map(|p| {
-                self.p.pos[(self.turn, p)]
+    // normal moves of the given piece types (prefiltered), then castles if the king is included
+    fn pseudo_legal_moves(&self, pieces: &[Piece]) -> impl Iterator<Item = Move> {
+        let normal = pieces
+            .iter()
+            .flat_map(move |&piece| {
+                self.p.pos[(self.turn, piece)]
                     .into_iter()
-                    .map(|src| {
-                        gen_dests(p, src)
-                            .into_iter()
-                            .map(|dest| {
-                                Move::Normal(SimplifiedMove {
-                                    piece: p,
-                                    src: src.into(),
-                                    dest: dest.into(),
-                                    hint_legal: false,
-                                })
-                            })
-                            .filter_map(|m| filter_pseudo_legal(self, m))
-                            .map(|m| {
-                                Position::simplified_move_outcomes(*self.p, &m, &task, &reduce)
+                    .flat_map(move |src| {
+                        self.gen_dests(piece, src).into_iter().map(move |dest| {
+                            Move::Normal(SimplifiedMove {
+                                piece,
+                                src: src.into(),
+                                dest: dest.into(),
+                                hint_legal: false,
+                                promotion: None,
                             })
-                            .filter_map(|x| x)
-                            .reduce(&reduce)
+                        })
                     })
-                    .filter_map(|x| x)
-                    .reduce(&reduce)
             })
-            .filter_map(|x| x)
-            .reduce(&reduce);
-        //TODO: add castling
-        let b = iter_castle_moves::<R>(self.p.castles, self)
-            .map(|m| Position::simplified_move_outcomes(*self.p, &m, &task, &reduce))
-            .filter_map(|x| x)
-            .reduce(&reduce);
-        match (a, b) {
-            (Some(x), Some(y)) => Some(reduce(x, y)),
-            (Some(x), None) => Some(x),
-            (None, Some(y)) => Some(y),
-            (None, None) => None,
-        }
+            .filter_map(move |m| filter_pseudo_legal(self, m));
+        let castles = pieces
+            .contains(&Piece::King)
+            .then(|| iter_castle_moves::<()>(self.p.castles, self))
+            .into_iter()
+            .flatten();
+        normal.chain(castles)
+    }
+
+    fn gen_moves_map<R>(
+        &self,
+        pieces: &[Piece],
+        task: impl Fn(&Position, &Move) -> R,
+        reduce: impl Fn(R, R) -> R,
+    ) -> Option<R> {
+        self.pseudo_legal_moves(pieces)
+            .filter_map(|m| Position::simplified_move_outcomes(*self.p, &m, &task, &reduce))
+            .reduce(&reduce)
     }
 
     fn compute_pinned(&mut self) {
@@ -480,10 +682,7 @@ impl<'a> AugmentedPos<'a> {
             pseudo_blockers,
         );
         self.pinned = if sliding_attacks & king != SpecialBB::Empty.declass() {
-            let trajectories = attacks::generate_queens(
-                king,
-                self.p.pos.occupied(opp) | self.p.pos.occupied(opp.other()),
-            );
+            let trajectories = attacks::generate_queens(king, self.p.occupied());
             trajectories & sliding_attacks | king
         } else {
             SpecialBB::Empty.declass()
@@ -495,7 +694,7 @@ impl<'a> AugmentedPos<'a> {
     }
 
     pub fn is_illegal(p: &Position) -> bool {
-        let blockers = p.pos.occupied(Player::White) | p.pos.occupied(Player::Black);
+        let blockers = p.occupied();
         let relevant_attacks = attacks::generate_bishops(
             p.pos[(p.turn(), Piece::Bishop)] | p.pos[(p.turn(), Piece::Queen)],
             blockers,
@@ -509,3 +708,198 @@ impl<'a> AugmentedPos<'a> {
         is_king_attacked
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(fen: &str, piece: Piece) -> Vec<String> {
+        let p = Position::from_fen(fen, "w", "-", "-", "0", "1");
+        let mut v: Vec<String> = p
+            .legal_moves_for(piece)
+            .iter()
+            .map(|m| format!("{m}"))
+            .collect();
+        v.sort();
+        v
+    }
+
+    #[test]
+    fn pinned_piece_stays_on_ray() {
+        // rook pinned on the e file
+        let rook = moves("4k3/4r3/8/8/8/8/4R3/4K3", Piece::Rook);
+        assert_eq!(rook, ["e2e3", "e2e4", "e2e5", "e2e6", "e2e7"]);
+        // a pinned knight cannot move at all
+        assert!(moves("4k3/4r3/8/8/8/8/4N3/4K3", Piece::Knight).is_empty());
+        // pinned diagonally, the bishop can still take the pinning bishop
+        let bishop = moves("4k3/8/8/b7/8/8/3B4/4K3", Piece::Bishop);
+        assert_eq!(bishop, ["d2a5", "d2b4", "d2c3"]);
+    }
+
+    #[test]
+    fn two_pieces_on_ray_not_pinned() {
+        let fen = "4k3/4r3/8/4B3/8/8/4R3/4K3";
+        assert_eq!(moves(fen, Piece::Rook).len(), 2 + 7);
+        assert_eq!(moves(fen, Piece::Bishop).len(), 13);
+        // the rook is only a candidate in the mask, checking the move once played frees it
+        let p = Position::from_fen(fen, "w", "-", "-", "0", "1");
+        let a = AugmentedPos::new(&p);
+        assert!(a.pinned & Square::e2 != SpecialBB::Empty.declass());
+        assert!(a.pinned & Square::e5 == SpecialBB::Empty.declass());
+    }
+
+    fn san(fen: &str, turn: &str, uci: &str) -> String {
+        let mut p = Position::from_fen(fen, turn, "-", "-", "0", "1");
+        p.getmove(uci).unwrap().expect(uci).to_san(&p)
+    }
+
+    #[test]
+    fn san_game() {
+        let game = [
+            ("e2e4", "e4"),
+            ("e7e5", "e5"),
+            ("g1f3", "Nf3"),
+            ("b8c6", "Nc6"),
+            ("f1b5", "Bb5"),
+            ("a7a6", "a6"),
+            ("b5c6", "Bxc6"),
+            ("d7c6", "dxc6"),
+            ("e1g1", "O-O"),
+            ("f7f6", "f6"),
+            ("d2d4", "d4"),
+            ("e5d4", "exd4"),
+            ("f3d4", "Nxd4"),
+            ("c6c5", "c5"),
+            ("d4b3", "Nb3"),
+            ("d8d1", "Qxd1"),
+            ("f1d1", "Rxd1"),
+            ("c8g4", "Bg4"),
+            ("d1d8", "Rd8+"),
+            ("e8d8", "Kxd8"),
+        ];
+        let mut p = Position::startingpos();
+        for (uci, expected) in game {
+            let m = p.getmove(uci).unwrap().expect(uci);
+            assert_eq!(m.to_san(&p), expected);
+            p = p.playmove(uci).unwrap().unwrap();
+        }
+
+        let mut p = Position::startingpos();
+        for uci in ["e2e4", "e7e5", "f1c4", "b8c6", "d1h5", "g8f6"] {
+            p = p.playmove(uci).unwrap().unwrap();
+        }
+        assert_eq!(p.getmove("h5f7").unwrap().unwrap().to_san(&p), "Qxf7#");
+    }
+
+    #[test]
+    fn san_special_moves() {
+        // disambiguation by file, by rank, by both
+        assert_eq!(san("7k/8/8/8/8/8/8/R2r1RK1", "w", "a1d1"), "Raxd1");
+        assert_eq!(san("7k/8/8/R7/8/8/8/R5K1", "w", "a1a3"), "R1a3");
+        assert_eq!(san("8/7k/8/8/8/Q7/8/Q1Q3K1", "w", "a1b2"), "Qa1b2");
+        // promotions
+        assert_eq!(san("3r3k/4P3/8/8/8/8/8/K7", "w", "e7e8q"), "e8=Q+");
+        assert_eq!(san("3r3k/4P3/8/8/8/8/8/K7", "w", "e7d8n"), "exd8=N");
+        let mut p = Position::from_fen("r3k3/8/8/8/8/8/8/4K3", "b", "q", "-", "0", "1");
+        assert_eq!(p.getmove("e8c8").unwrap().unwrap().to_san(&p), "O-O-O");
+        // en passant
+        let p = Position::from_fen("4k3/3p4/8/4P3/8/8/8/4K3", "b", "-", "-", "0", "1");
+        let mut p = p.playmove("d7d5").unwrap().unwrap();
+        assert_eq!(p.getmove("e5d6").unwrap().unwrap().to_san(&p), "exd6");
+    }
+
+    #[test]
+    fn list_issues_perft() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+        ] {
+            let mut p = Position::extract_fen(&mut fen.split_whitespace()).unwrap();
+            let moves = AugmentedPos::list_issues(&p);
+            assert_eq!(moves.len(), p.perft(1), "{fen}");
+            for m in moves {
+                let uci = format!("{m}");
+                assert_eq!(p.getmove(&uci).unwrap().map(|x| format!("{x}")), Some(uci));
+            }
+        }
+        let mut p = Position::startingpos();
+        assert!(p.getmove("e2e5").unwrap().is_none());
+        for uci in ["", "e2", "e2e9", "i2e4", "e7e8k", "e2e4q1"] {
+            assert!(p.getmove(uci).is_err(), "{uci}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod benchmarks {
+    extern crate test;
+
+    use super::*;
+    use std::hint::black_box;
+    use test::Bencher;
+
+    fn positions() -> Vec<Position> {
+        [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+        ]
+        .iter()
+        .map(|fen| Position::extract_fen(&mut fen.split_whitespace()).unwrap())
+        .collect()
+    }
+
+    // sums the destination indices so that the list is actually read back
+    fn checksum<'a>(moves: impl Iterator<Item = &'a Move>) -> usize {
+        moves
+            .map(|m| match m {
+                Move::Normal(x) => x.dest.0 as usize,
+                Move::Castle(_, _, _) => 1,
+            })
+            .sum()
+    }
+
+    // legal moves of the whole suite: pins, checks, castles, promotions and en passant
+    #[bench]
+    fn legal_moves_suite(b: &mut Bencher) {
+        let positions = positions();
+        let count = || -> usize { positions.iter().map(|p| p.legal_moves().len()).sum() };
+        let total = count();
+        // perft(1) of each position
+        assert_eq!(total, 20 + 48 + 14 + 6 + 44 + 46);
+        // throughput reported as MB/s, i.e. millions of moves per second
+        b.bytes = total as u64;
+        b.iter(|| assert_eq!(black_box(count()), total));
+    }
+
+    #[bench]
+    fn move_list_vec(b: &mut Bencher) {
+        let positions = positions();
+        b.iter(|| {
+            for p in positions.iter() {
+                let a = AugmentedPos::new(black_box(p));
+                let moves: Vec<Move> = a.pseudo_legal_moves(&PIECES).collect();
+                black_box(checksum(moves.iter()));
+            }
+        });
+    }
+
+    #[bench]
+    fn move_list_fastvec(b: &mut Bencher) {
+        let positions = positions();
+        b.iter(|| {
+            for p in positions.iter() {
+                let a = AugmentedPos::new(black_box(p));
+                let mut moves = FastVec::<64, Move>::new();
+                a.pseudo_legal_moves(&PIECES).for_each(|m| moves.push(m));
+                black_box(checksum(moves.iter()));
+            }
+        });
+    }
+}
@@ -0,0 +1,134 @@
+//! Static exchange evaluation
+//!
+//! Plays out every capture on the destination square of a move, least valuable attacker first,
+//! each side being free to stop capturing when it would lose material. Sliders revealed behind
+//! a capturing piece (x-rays) join the exchange. Promotions are not accounted for.
+use super::*;
+use movegen::attacks;
+
+// large enough for a king capture to never be worth a recapture
+const KING_VALUE: i32 = 20000;
+
+fn see_value(p: Piece) -> i32 {
+    match p {
+        Piece::King => KING_VALUE,
+        p => p.value() as i32,
+    }
+}
+
+impl Position {
+    // material won by the side to move playing this capture, once the exchange is resolved
+    pub fn see(&self, m: &Move) -> i32 {
+        let Move::Normal(x) = m else {
+            return 0;
+        };
+        let target: Bitboard<Square> = x.dest.into();
+        let src: Bitboard<Square> = x.src.into();
+        let turn = self.turn();
+
+        // gain[d]: balance for the side making the d-th capture if the exchange stopped there
+        let mut gain = [0; 32];
+        gain[0] = match self.pos.get((turn.other(), target)) {
+            Some(p) => see_value(p),
+            None if self.is_capture(m) => see_value(Piece::Pawn), // en passant
+            None => 0,
+        };
+        let mut on_target = see_value(x.piece);
+        let mut occupied = self.occupied() & !src.declass();
+        let mut side = turn.other();
+        let mut d = 0;
+        while let Some((piece, sq)) = self.least_valuable_attacker(target, side, occupied) {
+            d += 1;
+            gain[d] = on_target - gain[d - 1];
+            on_target = see_value(piece);
+            occupied &= !sq.declass();
+            side = side.other();
+            if d + 1 == gain.len() {
+                break;
+            }
+        }
+        // each side picks between stopping and going on with the exchange
+        while d > 0 {
+            gain[d - 1] = -std::cmp::max(-gain[d - 1], gain[d]);
+            d -= 1;
+        }
+        gain[0]
+    }
+
+    pub fn see_ge(&self, m: &Move, threshold: i32) -> bool {
+        self.see(m) >= threshold
+    }
+
+    // legal captures that do not lose material
+    pub fn winning_captures(&self) -> Vec<Move> {
+        self.legal_captures()
+            .into_iter()
+            .filter(|m| self.see_ge(m, 0))
+            .collect()
+    }
+
+    fn least_valuable_attacker(
+        &self,
+        target: Bitboard<Square>,
+        side: Player,
+        occupied: Bitboard<GenericBB>,
+    ) -> Option<(Piece, Bitboard<Square>)> {
+        let t = target.declass();
+        let diagonal = attacks::generate_bishops(t, occupied);
+        let straight = attacks::generate_rooks(t, occupied);
+        [
+            (Piece::Pawn, attacks::generate_pawns(t, side.other())),
+            (Piece::Knight, attacks::generate_knights(t)),
+            (Piece::Bishop, diagonal),
+            (Piece::Rook, straight),
+            (Piece::Queen, diagonal | straight),
+            (Piece::King, attacks::generate_king(target)),
+        ]
+        .into_iter()
+        .find_map(|(piece, from)| {
+            (self.pos[(side, piece)] & from & occupied)
+                .into_iter()
+                .next()
+                .map(|sq| (piece, sq))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(p: &Position, m: &str) -> Move {
+        *p.legal_captures()
+            .iter()
+            .find(|x| format!("{x}") == m)
+            .unwrap()
+    }
+
+    fn listed(moves: Vec<Move>, m: &str) -> bool {
+        moves.iter().any(|x| format!("{x}") == m)
+    }
+
+    #[test]
+    fn defended_pawn_not_winning() {
+        let p = Position::from_fen("4k3/8/2p5/3p4/8/8/3Q4/4K3", "w", "-", "-", "0", "1");
+        let qxd5 = capture(&p, "d2d5");
+        assert_eq!(p.see(&qxd5), 100 - 900);
+        assert!(listed(p.legal_captures(), "d2d5"));
+        assert!(!listed(p.winning_captures(), "d2d5"));
+    }
+
+    #[test]
+    fn exchanges() {
+        // undefended pawn
+        let p = Position::from_fen("4k3/8/8/3p4/8/8/3Q4/4K3", "w", "-", "-", "0", "1");
+        assert_eq!(p.see(&capture(&p, "d2d5")), 100);
+        assert_eq!(p.winning_captures().len(), 1);
+        // rook backed by a queen x-ray against a rook defending once
+        let p = Position::from_fen("3rk3/8/8/3n4/8/8/3R4/3QK3", "w", "-", "-", "0", "1");
+        assert_eq!(p.see(&capture(&p, "d2d5")), 300);
+        // pawn takes a defended knight
+        let p = Position::from_fen("4k3/8/2p5/3n4/4P3/8/8/4K3", "w", "-", "-", "0", "1");
+        assert_eq!(p.see(&capture(&p, "e4d5")), 300 - 100);
+    }
+}
@@ -5,6 +5,8 @@
 //! - Safety feature combines move count, castling rights, and en-passant for
 //!   detecting position changes
 //! Hash updates are performed in types.rs as pieces/game state changes
+//!
+//! The pawn key only hashes pawns, to index the pawn structure evaluation cache
 use crate::position::Piece;
 use crate::prelude::*;
 
@@ -25,6 +27,46 @@ impl Hashable<usize> for Position {
     }
 }
 
+// pawn placement only, used as the index of pawn hash tables
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PawnStructure {
+    key: usize,
+    pawns: [bb64; Player::COUNT],
+}
+
+impl Hashable<bb64> for PawnStructure {
+    fn hash(x: &Self) -> usize {
+        x.key
+    }
+
+    fn safety_feature(x: &Self) -> bb64 {
+        x.pawns[Player::White as usize] ^ x.pawns[Player::Black as usize].swap_bytes()
+    }
+}
+
+impl Position {
+    // XOR of the zobrist seeds of the pawns, equal for positions sharing their pawn structure
+    pub fn pawn_key(&self) -> usize {
+        let mut key = 0;
+        for pl in [Player::White, Player::Black] {
+            for sq in self.pos[(pl, Piece::Pawn)] {
+                key ^= zobrist_hash_square(sq, Piece::Pawn, pl);
+            }
+        }
+        key
+    }
+
+    pub fn pawn_structure(&self) -> PawnStructure {
+        PawnStructure {
+            key: self.pawn_key(),
+            pawns: [
+                self.pos[(Player::White, Piece::Pawn)].to_bb64(),
+                self.pos[(Player::Black, Piece::Pawn)].to_bb64(),
+            ],
+        }
+    }
+}
+
 type ZobristSeed = [[[usize; Player::COUNT]; Piece::COUNT]; Square::COUNT];
 
 #[deprecated]
@@ -2,68 +2,1206 @@
 pub struct MiniMaxMVP {}
 impl Search for MiniMaxMVP {
     async fn infinite<T: BasicEvaluation, Out: UciOutputStream>(
-        mut sigstop: futures::channel::oneshot::Receiver<()>,
+        sigstop: futures::channel::oneshot::Receiver<()>,
         pos: Position,
+        options: SearchOptions,
+        result: Option<futures::channel::oneshot::Sender<SearchResult>>,
     ) {
-        let mut depth: usize = 1;
-        let mut e = EvalState::new(Eval::Approx(ApproxEval::EQUAL));
-        loop {
-            e = tokio::select! {
-                _ = &mut sigstop => {
-                    break;
-                }
-                x = async move { let a = eval_minimax::<T>(&mut pos.clone(), depth); tokio::time::sleep(Duration::from_millis(0)).await; a} => { x
+        deepen::<Out>(eval_minimax::<T>, sigstop, pos, options, result).await
+    }
+
+    fn fixed_depth<T: BasicEvaluation>(mut pos: Position, depth: usize) -> EvalState {
+        eval_minimax::<T>(
+            &mut pos,
+            depth,
+            &mut DrawTable::default(),
+            &mut StopCheck::never(),
+        )
+    }
+}
 
-                }
-            };
-            Out::send_response(UciResponse::Info(format!("{e}").as_str())).unwrap();
-            depth += 1;
+// Same iterative deepening, each root move searched with alpha-beta pruning
+pub struct AlphaBeta {}
+impl Search for AlphaBeta {
+    async fn infinite<T: BasicEvaluation, Out: UciOutputStream>(
+        sigstop: futures::channel::oneshot::Receiver<()>,
+        pos: Position,
+        options: SearchOptions,
+        result: Option<futures::channel::oneshot::Sender<SearchResult>>,
+    ) {
+        deepen::<Out>(eval_alphabeta_full::<T>, sigstop, pos, options, result).await
+    }
+
+    fn fixed_depth<T: BasicEvaluation>(mut pos: Position, depth: usize) -> EvalState {
+        eval_alphabeta_full::<T>(
+            &mut pos,
+            depth,
+            &mut DrawTable::default(),
+            &mut StopCheck::never(),
+        )
+    }
+}
+
+// evaluation of a node at the given depth, minimax or alpha-beta
+type Node = fn(&mut Position, usize, &mut DrawTable, &mut StopCheck) -> EvalState;
+
+// iterative deepening until stopped, or until the depth / time limit of the options
+async fn deepen<Out: UciOutputStream>(
+    node: Node,
+    mut sigstop: futures::channel::oneshot::Receiver<()>,
+    pos: Position,
+    mut options: SearchOptions,
+    result: Option<futures::channel::oneshot::Sender<SearchResult>>,
+) {
+    // a forced move is played at once, after a single iteration for the info line
+    // an analysis or a search waiting for stop still goes deeper
+    if !options.analyse_mode && options.is_limited() && pos.legal_move_count() == 1 {
+        options.depth = Some(1);
+    }
+    let mut depth: usize = 1;
+    // best line of the last completed iteration, the one reported once stopped
+    let mut e = EvalState::new(Eval::Approx(ApproxEval::EQUAL));
+    let mut draws = DrawTable::with_history(options.history.clone());
+    draws.evals = Some(EvalCache::new(EVAL_CACHE_SIZE));
+    draws.contempt = Some((pos.turn(), options.contempt));
+    let mut stop = StopCheck::new(&mut sigstop, options.stop_interval);
+    let start = Instant::now();
+    let mut stability = Stability::default();
+    // already a third occurrence: the game is drawn, whatever move is played
+    let drawn = pos.threefold_in_game(&options.history);
+    let mut completed = 0;
+    loop {
+        // the first iteration always completes, so that there is a move to play
+        let movetime = options.movetime.filter(|_| depth > 1);
+        stop.set_deadline(movetime.map(|t| start + stability.scale(t)));
+        stop.set_node_limit(options.nodes.filter(|_| depth > 1));
+        // let the runtime breathe between iterations
+        tokio::task::yield_now().await;
+        if stop.poll() {
+            Out::send_debug(UciResponse::Debug("Received stop signal")).unwrap();
+            break;
+        }
+        let mut root = pos;
+        let evals = root_evals(node, &mut root, depth, &mut draws, &mut stop);
+        // an interrupted iteration is incomplete, the previous one is kept
+        if stop.stopped() {
+            Out::send_debug(UciResponse::Debug("Received stop signal")).unwrap();
+            break;
+        }
+        let margin = best_margin(pos.turn(), &evals);
+        e = evals
+            .iter()
+            .cloned()
+            .reduce(|e0, e1| EvalState::pick_best_for(pos.turn(), e0, e1))
+            .unwrap_or_else(|| no_moves(&pos, &draws));
+        // drawn whatever the moves are worth, the depth is kept
+        if drawn {
+            e.eval = draws.draw(e.eval.depth());
         }
-        Out::send_debug(crate::uci::UciResponse::Debug("Received stop signal")).unwrap();
-        Out::send_response(crate::uci::UciResponse::Info(format!("{e}").as_str())).unwrap();
+        stability.update(e.pv.moves().first().map(Move::from_to), margin);
+        completed = depth;
+        let update = search_update(completed, &e, &stop, start, &draws);
+        if options.show_info == ShowInfo::Always {
+            Out::send_response(UciResponse::Info(update.to_string().as_str())).unwrap();
+        }
+        if let Some(updates) = &options.updates {
+            // the receiver may have been dropped, the search goes on
+            let _ = updates.unbounded_send(update);
+        }
+        if options.analyse_mode {
+            send_refutations::<Out>(&pos, &e, &evals);
+        }
+        if options.depth.is_some_and(|max| depth >= max) {
+            break;
+        }
+        depth += 1;
+    }
+    let update = search_update(completed, &e, &stop, start, &draws);
+    Out::send_response(UciResponse::Info(update.to_string().as_str())).unwrap();
+
+    // stopped before a first iteration completed, any legal move beats none
+    let best = e.pv.moves().first().copied().or_else(|| {
+        let (noisy, quiet) = pos.staged_moves();
+        noisy.into_iter().chain(quiet).next()
+    });
+    Out::send_response(UciResponse::BestMove(best)).unwrap();
+    if let Some(result) = result {
+        // the receiver may have been dropped, the result is then simply discarded
+        let _ = result.send((best, e.eval));
     }
 }
 
-use std::time::Duration;
+// best line of the last completed depth, the search having started at start
+fn search_update(
+    depth: usize,
+    e: &EvalState,
+    stop: &StopCheck,
+    start: Instant,
+    draws: &DrawTable,
+) -> SearchUpdate {
+    SearchUpdate {
+        depth,
+        score: e.eval,
+        pv: e.pv.clone(),
+        nodes: stop.nodes(),
+        time: start.elapsed(),
+        nps: stop.nps(start),
+        hashfull: draws.hashfull(),
+    }
+}
 
-use log::warn;
+use std::{collections::HashSet, time::Instant};
 
 use crate::{
     AugmentedPos, PositionSpec,
     eval::{ApproxEval, BasicEvaluation, Eval, EvalState},
-    position::Position,
+    player::Player,
+    position::{Move, Position, RepetitionHistory},
+    tt::{EvalCache, EvalInfo},
     uci::{UciOutputStream, UciResponse},
 };
 
-use super::Search;
+use super::{
+    Search, SearchOptions, SearchResult, SearchUpdate, ShowInfo, StopCheck,
+    ordering::ordered_moves, time::Stability,
+};
+
+// draws met in a search: the path to the node for repetitions, and how they score
+#[derive(Default)]
+pub struct DrawTable {
+    // repetition keys of the positions drawn whatever the path to them, found in this search
+    keys: HashSet<usize>,
+    hits: usize,
+    // positions from the start of the game to the node searched, repetitions depend on it
+    path: RepetitionHistory,
+    // exact evals of the searched nodes, when the search keeps them
+    evals: Option<EvalCache>,
+    // side searching and its contempt, draws score 0 without
+    contempt: Option<(Player, i32)>,
+}
+
+// entries of the eval table of a timed or infinite search
+const EVAL_CACHE_SIZE: usize = 1 << 16;
+
+impl DrawTable {
+    pub fn with_history(path: RepetitionHistory) -> Self {
+        Self {
+            path,
+            ..Default::default()
+        }
+    }
+
+    fn is_draw(&mut self, pos: &Position) -> bool {
+        let key = pos.repetition_key();
+        if self.keys.contains(&key) {
+            self.hits += 1;
+            return true;
+        }
+        // the key ignores the fifty-move counter, such draws cannot be recorded
+        if pos.is_fifty_move_draw() {
+            return true;
+        }
+        if pos.is_insufficient_material() {
+            self.keys.insert(key);
+            return true;
+        }
+        // drawn by the way it is reached, not to be recorded either
+        self.path.is_threefold(pos)
+    }
+
+    // the eval of pos searched depth plies deep only depends on pos when no draw rule can
+    // involve the path to it: right after a capture or a pawn move, and far enough from the
+    // fifty-move limit
+    fn path_independent(pos: &Position, depth: usize) -> bool {
+        pos.fifty_mv == 0 && depth < 100
+    }
+
+    // e is the exact eval of pos searched at the given depth, relative to pos
+    fn record(&mut self, pos: &Position, e: &EvalState, depth: usize) {
+        if !Self::path_independent(pos, depth) {
+            return;
+        }
+        if let Some(evals) = &mut self.evals {
+            evals.push(pos, &EvalInfo::new(e.eval.score(0), 0, depth as u32));
+        }
+    }
+
+    // eval of pos recorded at least depth plies deep, relative to pos
+    fn probe(&self, pos: &Position, depth: usize) -> Option<EvalState> {
+        let evals = self.evals.as_ref()?;
+        if !Self::path_independent(pos, depth) {
+            return None;
+        }
+        evals[pos]
+            .filter(|info| info.depth as usize >= depth)
+            .map(|info| EvalState::new(Eval::from_score(info.score(0), 0)))
+    }
+
+    // score of the draws met in the search, depth plies deep
+    fn draw(&self, depth: usize) -> Eval {
+        let (engine, contempt) = self.contempt.unwrap_or((Player::White, 0));
+        Position::material_draw_score(engine, contempt, depth)
+    }
 
-pub fn eval_minimax<T: BasicEvaluation>(pos: &Position, depth: usize) -> EvalState {
+    // occupied entries per thousand of the eval table (UCI hashfull), None without one
+    fn hashfull(&self) -> Option<usize> {
+        self.evals.as_ref().map(EvalCache::fill_permille)
+    }
+}
+
+pub fn eval_minimax<T: BasicEvaluation>(
+    pos: &mut Position,
+    depth: usize,
+    draws: &mut DrawTable,
+    stop: &mut StopCheck,
+) -> EvalState {
     //#[cfg(debug_assertions)]
     //pos.assert_squares_occupied_only_once();
+    // the result is discarded once stopped, anything cheap does
+    if stop.tick() {
+        return EvalState::new(Eval::draw());
+    }
+    if draws.is_draw(pos) {
+        return EvalState::new(draws.draw(0));
+    }
+    if let Some(e) = draws.probe(pos, depth) {
+        return e;
+    }
     match depth {
-        // TODO: add quiescent search for depth 1
-        0 => EvalState::new(T::eval(pos)),
+        0 => quiescence::<T>(pos, None, None, stop),
         _ => {
             let turn = pos.turn();
 
-            let e = AugmentedPos::map_issues(
+            draws.path.push(pos);
+            let e = AugmentedPos::map_issues_mut(
                 pos,
                 |p, _x| {
-                    let mut a = eval_minimax::<T>(p, depth - 1);
+                    let mut a = eval_minimax::<T>(p, depth - 1, draws, stop);
                     a.nest(*_x);
                     a
                 },
-                |e0, e1| EvalState::pick_best_for(pos.turn(), e0, e1),
+                |e0, e1| EvalState::pick_best_for(turn, e0, e1),
             );
+            draws.path.pop();
 
-            let e = match e {
-                Some(x) => x,
-                None => {
-                    warn!("This could also be a draw, TODO");
-                    EvalState::new(Eval::m0(turn.other()))
-                }
-            };
+            let e = e.unwrap_or_else(|| no_moves(pos, draws));
+            // an interrupted search returns anything, not worth recording
+            if !stop.stopped() {
+                draws.record(pos, &e, depth);
+            }
             e
         }
     }
 }
+
+// node without legal moves: mated when in check, stalemate otherwise
+fn no_moves(pos: &Position, draws: &DrawTable) -> EvalState {
+    match pos.is_check() {
+        true => EvalState::new(Eval::m0(pos.turn().other())),
+        false => EvalState::new(draws.draw(0)),
+    }
+}
+
+// same result as eval_minimax, skipping the moves that cannot change it
+// alpha is the best eval white is assured of, beta the best one black is, None if unbounded
+// both are seen from pos: the bounds of the parent node are unnested for its children
+pub fn eval_alphabeta<T: BasicEvaluation>(
+    pos: &mut Position,
+    depth: usize,
+    mut alpha: Option<Eval>,
+    mut beta: Option<Eval>,
+    draws: &mut DrawTable,
+    stop: &mut StopCheck,
+) -> EvalState {
+    if stop.tick() {
+        return EvalState::new(Eval::draw());
+    }
+    if draws.is_draw(pos) {
+        return EvalState::new(draws.draw(0));
+    }
+    if depth == 0 {
+        return quiescence::<T>(pos, alpha, beta, stop);
+    }
+    // exact evals only are recorded, within any bounds
+    if let Some(e) = draws.probe(pos, depth) {
+        return e;
+    }
+    let turn = pos.turn();
+    // the most promising moves first, for earlier cutoffs
+    let moves = ordered_moves(pos);
+    let own_bound = match turn {
+        Player::White => alpha,
+        Player::Black => beta,
+    };
+
+    draws.path.push(pos);
+    let mut best: Option<EvalState> = None;
+    let mut cut = false;
+    for m in moves {
+        let undo = pos.stack(&m, m.promotion());
+        let mut e = eval_alphabeta::<T>(
+            pos,
+            depth - 1,
+            alpha.map(Eval::unnest),
+            beta.map(Eval::unnest),
+            draws,
+            stop,
+        );
+        pos.unstack(&m, undo);
+        e.nest(m);
+
+        let cutoff = raise_bound(turn, &mut alpha, &mut beta, e.eval);
+        best = Some(match best {
+            Some(b) => EvalState::pick_best_for(turn, b, e),
+            None => e,
+        });
+        if cutoff {
+            cut = true;
+            break;
+        }
+    }
+    draws.path.pop();
+
+    let best = best.unwrap_or_else(|| no_moves(pos, draws));
+    // exact when every move was searched and one raised the bound of the player to move,
+    // otherwise only a bound of the eval of pos is known
+    let exact = !cut && own_bound.is_none_or(|b| Eval::better_for(turn, best.eval, b));
+    if exact && !stop.stopped() {
+        draws.record(pos, &best, depth);
+    }
+    best
+}
+
+// raises the bound of the player to move to e, true if the node can be cut off
+fn raise_bound(turn: Player, alpha: &mut Option<Eval>, beta: &mut Option<Eval>, e: Eval) -> bool {
+    let bound = match turn {
+        Player::White => &mut *alpha,
+        Player::Black => &mut *beta,
+    };
+    if bound.is_none_or(|b| Eval::better_for(turn, e, b)) {
+        *bound = Some(e);
+    }
+    // the opponent avoids this node, whatever the remaining moves are worth
+    matches!((*alpha, *beta), (Some(a), Some(b)) if !Eval::better_for(Player::White, b, a))
+}
+
+// leaf evaluation, once the captures and promotions are played out
+// the player to move may stand pat: keep the static eval rather than capture
+// the capture sequence is not part of the pv, nor of the depth reported
+pub fn quiescence<T: BasicEvaluation>(
+    pos: &mut Position,
+    mut alpha: Option<Eval>,
+    mut beta: Option<Eval>,
+    stop: &mut StopCheck,
+) -> EvalState {
+    let turn = pos.turn();
+    let mut best = T::eval(pos);
+    if raise_bound(turn, &mut alpha, &mut beta, best) {
+        return EvalState::new(best);
+    }
+    let (noisy, _quiet) = pos.staged_moves();
+    for m in noisy {
+        // the node itself is counted by the caller, its captures here
+        if stop.tick() {
+            break;
+        }
+        let undo = pos.stack(&m, m.promotion());
+        let e = quiescence::<T>(pos, alpha, beta, stop).eval;
+        pos.unstack(&m, undo);
+        if Eval::better_for(turn, e, best) {
+            best = e;
+        }
+        if raise_bound(turn, &mut alpha, &mut beta, e) {
+            break;
+        }
+    }
+    EvalState::new(best)
+}
+
+fn eval_alphabeta_full<T: BasicEvaluation>(
+    pos: &mut Position,
+    depth: usize,
+    draws: &mut DrawTable,
+    stop: &mut StopCheck,
+) -> EvalState {
+    eval_alphabeta::<T>(pos, depth, None, None, draws, stop)
+}
+
+// evaluation of each root move, its pv holding the opponent's best reply
+fn root_evals(
+    node: Node,
+    pos: &mut Position,
+    depth: usize,
+    draws: &mut DrawTable,
+    stop: &mut StopCheck,
+) -> Vec<EvalState> {
+    draws.path.push(pos);
+    let evals = AugmentedPos::map_issues_mut(
+        pos,
+        |p, m| {
+            let mut a = node(p, depth - 1, draws, stop);
+            a.nest(*m);
+            vec![a]
+        },
+        |mut a, mut b| {
+            a.append(&mut b);
+            a
+        },
+    )
+    .unwrap_or_default();
+    draws.path.pop();
+    evals
+}
+
+// lead of the best root move over the second best one, for the player to move
+fn best_margin(turn: Player, evals: &[EvalState]) -> i32 {
+    let mut scores: Vec<i32> = evals
+        .iter()
+        .map(|e| match turn {
+            Player::White => e.eval.score(0),
+            Player::Black => -e.eval.score(0),
+        })
+        .collect();
+    scores.sort_unstable_by(|a, b| b.cmp(a));
+    match scores[..] {
+        [best, second, ..] => best - second,
+        // nothing to think about
+        _ => i32::MAX,
+    }
+}
+
+// UCI_AnalyseMode: send the refuting line of every root move worse than the best one
+// evals are the ones of the root moves, as searched by the last iteration
+pub fn send_refutations<Out: UciOutputStream>(
+    pos: &Position,
+    best: &EvalState,
+    evals: &[EvalState],
+) {
+    for e in evals {
+        if Eval::better_for(pos.turn(), best.eval, e.eval) {
+            Out::send_response(UciResponse::Refutation(e.pv.moves())).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate test;
+
+    use super::*;
+    use crate::{
+        eval::MaterialBalance,
+        uci::{CaptureUciStream, NullUciStream},
+    };
+    use std::time::Duration;
+    use test::Bencher;
+
+    #[bench]
+    fn minimax_startpos_3(b: &mut Bencher) {
+        let mut pos = Position::startingpos();
+        b.iter(|| {
+            eval_minimax::<MaterialBalance>(
+                &mut pos,
+                std::hint::black_box(3),
+                &mut DrawTable::default(),
+                &mut StopCheck::never(),
+            )
+        });
+    }
+
+    #[bench]
+    fn alphabeta_startpos_3(b: &mut Bencher) {
+        let mut pos = Position::startingpos();
+        b.iter(|| {
+            eval_alphabeta::<MaterialBalance>(
+                &mut pos,
+                std::hint::black_box(3),
+                None,
+                None,
+                &mut DrawTable::default(),
+                &mut StopCheck::never(),
+            )
+        });
+    }
+
+    #[test]
+    fn quiescence_hanging_queen() {
+        // black to move, the white queen is taken by the e6 pawn
+        let mut pos = Position::from_fen("4k3/8/4p3/3Q4/8/8/8/4K3", "b", "-", "-", "0", "1");
+        let raw = MaterialBalance::eval(&pos).score(0);
+        let e = quiescence::<MaterialBalance>(&mut pos, None, None, &mut StopCheck::never());
+        assert!(raw > 700, "{raw}");
+        assert!(e.eval.score(0) <= 0, "{}", e.eval);
+        assert!(e.pv.moves().is_empty());
+        assert!(pos == Position::from_fen("4k3/8/4p3/3Q4/8/8/8/4K3", "b", "-", "-", "0", "1"));
+
+        // white to move at the leaf: standing pat beats Qxe6+, the queen is then taken back
+        let mut pos = Position::from_fen("4k3/5p2/4p3/3Q4/8/8/8/4K3", "w", "-", "-", "0", "1");
+        let stand = MaterialBalance::eval(&pos).score(0);
+        let e = quiescence::<MaterialBalance>(&mut pos, None, None, &mut StopCheck::never());
+        assert_eq!(e.eval.score(0), stand);
+    }
+
+    #[test]
+    fn alphabeta_matches_minimax() {
+        let positions = [
+            // knight fork of king and queen
+            Position::from_fen("4k3/8/2q5/8/3N4/8/8/4K3", "w", "-", "-", "0", "1"),
+            // back rank mate in one, Re8#
+            Position::from_fen("6k1/5ppp/8/8/8/8/5PPP/4R1K1", "w", "-", "-", "0", "1"),
+            // black to move, hanging rook and a promotion
+            Position::from_fen("4k3/8/8/8/3r4/8/1p2Q3/4K3", "b", "-", "-", "0", "1"),
+            Position::startingpos(),
+        ];
+        for pos in positions {
+            for depth in 1..=3 {
+                let (mut a, mut b) = (pos, pos);
+                let full = eval_minimax::<MaterialBalance>(
+                    &mut a,
+                    depth,
+                    &mut DrawTable::default(),
+                    &mut StopCheck::never(),
+                );
+                let mut stop = StopCheck::never();
+                let pruned = eval_alphabeta::<MaterialBalance>(
+                    &mut b,
+                    depth,
+                    None,
+                    None,
+                    &mut DrawTable::default(),
+                    &mut stop,
+                );
+                assert_eq!(full.eval.score(0), pruned.eval.score(0), "depth {depth}");
+                assert!(a == pos && b == pos);
+            }
+        }
+        // Re8# found, and fewer nodes visited
+        let mut pos = positions[1];
+        let e = AlphaBeta::fixed_depth::<MaterialBalance>(pos, 3);
+        assert_eq!(format!("{}", e.eval), "#1");
+        assert_eq!(format!("{}", e.pv.moves()[0]), "e1e8");
+        let (mut full, mut pruned) = (StopCheck::never(), StopCheck::never());
+        eval_minimax::<MaterialBalance>(&mut pos, 3, &mut DrawTable::default(), &mut full);
+        eval_alphabeta::<MaterialBalance>(
+            &mut pos,
+            3,
+            None,
+            None,
+            &mut DrawTable::default(),
+            &mut pruned,
+        );
+        assert!(
+            pruned.nodes() < full.nodes(),
+            "{} {}",
+            pruned.nodes(),
+            full.nodes()
+        );
+    }
+
+    #[tokio::test]
+    async fn depth_limited_result() {
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let (send, recv) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            depth: Some(2),
+            ..Default::default()
+        };
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            Position::startingpos(),
+            options,
+            Some(send),
+        )
+        .await;
+        let out = CaptureUciStream::take();
+
+        let (best, _eval) = recv.await.unwrap();
+        let best = best.expect("startpos has legal moves");
+        assert_eq!(out.last(), Some(&format!("bestmove {best}\n")), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn bestmove_last() {
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            depth: Some(2),
+            analyse_mode: true,
+            ..Default::default()
+        };
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            Position::startingpos(),
+            options,
+            None,
+        )
+        .await;
+        let out = CaptureUciStream::take();
+        // info and refutation lines, then a single bestmove
+        let (last, lines) = out.split_last().unwrap();
+        assert!(last.starts_with("bestmove "), "{out:?}");
+        assert!(lines.iter().all(|l| l.starts_with("info ")), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn bestmove_when_stopped_early() {
+        // stop received before the first iteration
+        let (sendstop, sigstop) = futures::channel::oneshot::channel();
+        sendstop.send(()).unwrap();
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            Position::startingpos(),
+            SearchOptions::default(),
+            None,
+        )
+        .await;
+        let out = CaptureUciStream::take();
+        let last = out.last().unwrap();
+        assert!(last.starts_with("bestmove "), "{out:?}");
+        assert_ne!(last, "bestmove (none)\n");
+
+        // stalemate
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let pos = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8", "b", "-", "-", "0", "1");
+        let options = SearchOptions {
+            depth: Some(1),
+            ..Default::default()
+        };
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(sigstop, pos, options, None)
+            .await;
+        let out = CaptureUciStream::take();
+        assert_eq!(out.last().unwrap(), "bestmove (none)\n");
+    }
+
+    #[tokio::test]
+    async fn final_info_only() {
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            depth: Some(3),
+            show_info: ShowInfo::Final,
+            ..Default::default()
+        };
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            Position::startingpos(),
+            options,
+            None,
+        )
+        .await;
+        let out = CaptureUciStream::take();
+        assert_eq!(out.len(), 2, "{out:?}");
+        assert!(out[0].starts_with("info depth 3 "), "{out:?}");
+        assert!(out[1].starts_with("bestmove "), "{out:?}");
+    }
+
+    #[test]
+    fn stop_within_node_window() {
+        let (sendstop, mut sigstop) = futures::channel::oneshot::channel();
+        sendstop.send(()).unwrap();
+        let mut stop = StopCheck::new(&mut sigstop, 64);
+        // millions of nodes if not interrupted
+        let mut pos = Position::startingpos();
+        eval_minimax::<MaterialBalance>(&mut pos, 6, &mut DrawTable::default(), &mut stop);
+        assert!(stop.stopped());
+        // the siblings left on the current line are skipped at once
+        assert!(stop.nodes() <= 64 + 6 * 40, "{}", stop.nodes());
+        assert!(pos == Position::startingpos());
+    }
+
+    #[test]
+    fn node_count() {
+        // no capture within reach: every node is a perft node, from depth 0 to 3
+        let mut pos = Position::from_fen("k7/8/8/8/8/8/P7/7K", "w", "-", "-", "0", "1");
+        let mut stop = StopCheck::never();
+        let counter = stop.counter();
+        eval_minimax::<MaterialBalance>(&mut pos, 3, &mut DrawTable::default(), &mut stop);
+        assert_eq!(stop.nodes(), 1 + 5 + 15 + 96);
+        // read from another thread
+        let seen = std::thread::spawn(move || counter.load(std::sync::atomic::Ordering::Relaxed));
+        assert_eq!(seen.join().unwrap(), 117);
+    }
+
+    #[tokio::test]
+    async fn info_fields_per_depth() {
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            depth: Some(2),
+            ..Default::default()
+        };
+        let pos = Position::from_fen("k7/8/8/8/8/8/P7/7K", "w", "-", "-", "0", "1");
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(sigstop, pos, options, None)
+            .await;
+        let out = CaptureUciStream::take();
+        assert_eq!(out.len(), 4, "{out:?}");
+        // nodes cumulated over the iterations, the root is counted by its moves only
+        for (line, nodes) in out.iter().zip(["5", "25", "25"]) {
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let at = |field| tokens.iter().position(|t| *t == field).expect(line);
+            let order = ["info", "depth", "score", "time", "nodes", "nps", "pv"].map(at);
+            assert!(order.is_sorted(), "{line}");
+            assert_eq!(tokens[at("nodes") + 1], nodes, "{line}");
+            tokens[at("time") + 1].parse::<u64>().expect(line);
+            tokens[at("nps") + 1].parse::<u64>().expect(line);
+            // the pv runs until the end of the line
+            assert!(
+                tokens[at("pv") + 1..].iter().all(|m| m.len() >= 4),
+                "{line}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn hashfull_rises() {
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            depth: Some(4),
+            ..Default::default()
+        };
+        CaptureUciStream::take();
+        // every minimax node is exact, recorded when reached by a capture or a pawn move
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            Position::startingpos(),
+            options,
+            None,
+        )
+        .await;
+        let out = CaptureUciStream::take();
+        let hashfull: Vec<usize> = out
+            .iter()
+            .filter_map(|line| {
+                let tokens: Vec<&str> = line.split_whitespace().collect();
+                let at = tokens.iter().position(|t| *t == "hashfull")?;
+                Some(tokens[at + 1].parse().expect(line))
+            })
+            .collect();
+        // one per depth, then the final line
+        assert_eq!(hashfull.len(), 5, "{out:?}");
+        assert!(hashfull.is_sorted(), "{out:?}");
+        assert!(hashfull[4] > 0, "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn node_budget() {
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let (send, recv) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            nodes: Some(2000),
+            ..Default::default()
+        };
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            Position::startingpos(),
+            options,
+            Some(send),
+        )
+        .await;
+        let out = CaptureUciStream::take();
+        let (best, _) = recv.await.unwrap();
+        assert!(best.is_some());
+        // depth 3 from the start position is well over the budget
+        assert!(
+            out.iter().all(|l| !l.starts_with("info depth 3 ")),
+            "{out:?}"
+        );
+        assert!(out.last().unwrap().starts_with("bestmove "), "{out:?}");
+
+        let mut stop = StopCheck::never();
+        stop.set_node_limit(Some(10));
+        let mut pos = Position::startingpos();
+        eval_minimax::<MaterialBalance>(&mut pos, 3, &mut DrawTable::default(), &mut stop);
+        assert!(stop.stopped());
+        // the siblings left on the current line are skipped at once
+        assert!(stop.nodes() <= 10 + 3 * 40, "{}", stop.nodes());
+    }
+
+    #[tokio::test]
+    async fn infinite_until_stopped() {
+        let (sendstop, sigstop) = futures::channel::oneshot::channel();
+        let (send, recv) = futures::channel::oneshot::channel();
+        let search = tokio::spawn(MiniMaxMVP::infinite::<MaterialBalance, NullUciStream>(
+            sigstop,
+            Position::startingpos(),
+            SearchOptions::default(),
+            Some(send),
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        sendstop.send(()).unwrap();
+        let (best, _) = recv.await.unwrap();
+        assert!(best.is_some());
+        search.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn easy_move_played_early() {
+        // the hanging queen is worth much more than any other move
+        let pos = Position::from_fen("4k3/8/8/3q4/8/8/8/3QK3", "w", "-", "-", "0", "1");
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let (send, recv) = futures::channel::oneshot::channel();
+        let movetime = Duration::from_secs(4);
+        let options = SearchOptions {
+            movetime: Some(movetime),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        MiniMaxMVP::infinite::<MaterialBalance, NullUciStream>(sigstop, pos, options, Some(send))
+            .await;
+        assert!(start.elapsed() < movetime / 2, "{:?}", start.elapsed());
+        let (best, _) = recv.await.unwrap();
+        assert_eq!(format!("{}", best.unwrap()), "d1d5");
+    }
+
+    #[test]
+    fn fixed_depth_startpos() {
+        let e = MiniMaxMVP::fixed_depth::<MaterialBalance>(Position::startingpos(), 2);
+        assert_eq!(e.eval.score(0), 0);
+        assert_eq!(e.pv.moves().len(), 2);
+    }
+
+    #[test]
+    fn mate_through_transposition() {
+        use crate::tt::{EvalCache, EvalInfo};
+
+        // Qa8#
+        let mut pos = Position::from_fen("7k/8/6K1/8/8/8/8/Q7", "w", "-", "-", "0", "1");
+        let e = eval_minimax::<MaterialBalance>(
+            &mut pos,
+            2,
+            &mut DrawTable::default(),
+            &mut StopCheck::never(),
+        );
+        assert_eq!(format!("{}", e.eval), "#1");
+
+        // found 3 plies after the root of a first search
+        let mut tt = EvalCache::new(16);
+        tt.push(&pos, &EvalInfo::new(e.eval.score(3), 3, 2));
+
+        // reached again 5 plies after the root of another search
+        let info = tt[&pos].unwrap();
+        assert_eq!(format!("{}", Eval::from_score(info.score(5), 5)), "#1");
+        // mate in 6 plies from that root
+        assert_eq!(format!("{}", Eval::from_score(info.score(5), 0)), "#3");
+        assert_eq!(format!("{}", Eval::from_score(info.score(3), 0)), "#2");
+        // black mates are mirrored
+        let black = Eval::from_score(EvalInfo::new(-e.eval.score(3), 3, 2).score(5), 0);
+        assert_eq!(format!("{black}"), "#-3");
+    }
+
+    #[test]
+    fn eval_table_probe() {
+        use crate::tt::EvalCache;
+
+        let pos = Position::from_fen("7k/8/8/8/8/8/8/Q6K", "w", "-", "-", "0", "1");
+        let mut draws = DrawTable {
+            evals: Some(EvalCache::new(16)),
+            ..Default::default()
+        };
+        draws.record(&pos, &EvalState::new(Eval::from_score(1234, 0)), 3);
+
+        // recorded deep enough, the eval is taken from the table
+        let e = eval_minimax::<MaterialBalance>(
+            &mut pos.clone(),
+            2,
+            &mut draws,
+            &mut StopCheck::never(),
+        );
+        assert_eq!(e.eval.score(0), 1234);
+        let e = eval_alphabeta::<MaterialBalance>(
+            &mut pos.clone(),
+            3,
+            None,
+            None,
+            &mut draws,
+            &mut StopCheck::never(),
+        );
+        assert_eq!(e.eval.score(0), 1234);
+        let e = eval_alphabeta::<MaterialBalance>(
+            &mut pos.clone(),
+            4,
+            None,
+            None,
+            &mut draws,
+            &mut StopCheck::never(),
+        );
+        assert_ne!(e.eval.score(0), 1234);
+
+        // reached after a quiet move, the path could hold repetitions: searched again
+        let mut quiet = Position::from_fen("7k/8/8/8/8/8/8/Q6K", "w", "-", "-", "1", "1");
+        draws.record(&quiet, &EvalState::new(Eval::from_score(1234, 0)), 3);
+        let e = eval_minimax::<MaterialBalance>(&mut quiet, 2, &mut draws, &mut StopCheck::never());
+        assert_ne!(e.eval.score(0), 1234);
+    }
+
+    #[test]
+    fn refutation_of_hanging_queen() {
+        // white queen attacked by a pawn
+        let mut pos = Position::from_fen("7k/8/8/2p5/3Q4/8/8/7K", "w", "-", "-", "0", "1");
+        let best = eval_minimax::<MaterialBalance>(
+            &mut pos,
+            2,
+            &mut DrawTable::default(),
+            &mut StopCheck::never(),
+        );
+        let evals = root_evals(
+            eval_minimax::<MaterialBalance>,
+            &mut pos,
+            2,
+            &mut DrawTable::default(),
+            &mut StopCheck::never(),
+        );
+        CaptureUciStream::take();
+        send_refutations::<CaptureUciStream>(&pos, &best, &evals);
+        let out = CaptureUciStream::take();
+        assert!(
+            out.contains(&"info refutation h1g1 c5d4\n".to_string()),
+            "{out:?}"
+        );
+        // capturing the pawn is not refuted
+        assert!(!out.iter().any(|l| l.starts_with("info refutation d4c5")));
+    }
+
+    #[test]
+    fn repetition_is_draw() {
+        // white a queen up, but the position already occurred twice
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2);
+        let start = Position::from_fen("1nb1kbn1/8/8/8/8/8/8/QNB1KBN1", "w", "-", "-", "0", "1");
+        let (mut pos, history) = start.apply_uci_moves_with_history(&moves).unwrap();
+        let e = eval_minimax::<MaterialBalance>(
+            &mut pos,
+            0,
+            &mut DrawTable::with_history(history.clone()),
+            &mut StopCheck::never(),
+        );
+        assert_eq!(e.eval.score(0), 0);
+        // without the history, the material counts
+        let e = eval_minimax::<MaterialBalance>(
+            &mut pos,
+            0,
+            &mut DrawTable::default(),
+            &mut StopCheck::never(),
+        );
+        assert!(e.eval.score(0) > 0);
+
+        // one step before, black can repeat by force: Nf6-g8 is a draw, not a loss
+        let (mut pos, history) = start.apply_uci_moves_with_history(&moves[..7]).unwrap();
+        let mut draws = DrawTable::with_history(history);
+        let evals = root_evals(
+            eval_minimax::<MaterialBalance>,
+            &mut pos,
+            1,
+            &mut draws,
+            &mut StopCheck::never(),
+        );
+        let back = evals
+            .iter()
+            .find(|e| format!("{}", e.pv.moves()[0]) == "f6g8")
+            .unwrap();
+        assert_eq!(back.eval.score(0), 0);
+    }
+
+    #[test]
+    fn draws_use_contempt() {
+        let repeated = ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2);
+        let start = Position::from_fen("1nb1kbn1/8/8/8/8/8/8/QNB1KBN1", "w", "-", "-", "0", "1");
+        let (repetition, history) = start.apply_uci_moves_with_history(&repeated).unwrap();
+        let fifty_moves = Position::from_fen("7k/8/8/8/8/8/8/Q6K", "w", "-", "-", "100", "80");
+        let stalemate = Position::from_fen("7k/5Q2/6K1/8/8/8/8/8", "b", "-", "-", "0", "1");
+        let insufficient = Position::from_fen("7k/8/8/8/8/8/8/6NK", "w", "-", "-", "0", "1");
+        for (engine, contempt, cp) in [
+            (Player::White, 0, 0),
+            (Player::White, 25, -25),
+            (Player::Black, 25, 25),
+        ] {
+            for (mut pos, depth) in [
+                (repetition, 0),
+                (fifty_moves, 0),
+                (stalemate, 1),
+                (insufficient, 0),
+            ] {
+                let mut draws = DrawTable::with_history(history.clone());
+                draws.contempt = Some((engine, contempt));
+                let e = eval_alphabeta_full::<MaterialBalance>(
+                    &mut pos,
+                    depth,
+                    &mut draws,
+                    &mut StopCheck::never(),
+                );
+                assert_eq!(e.eval.score(0), cp, "{}", pos.to_fen());
+            }
+        }
+    }
+
+    #[test]
+    fn fifty_move_draw_in_search() {
+        // a rook up, the hundredth half move without capture nor pawn move comes next
+        let moves = ["a1a2", "e8d8", "a2a1", "d8e8"].repeat(25);
+        let p = Position::from_fen("4k3/8/8/8/8/8/8/R3K3", "w", "-", "-", "0", "1");
+        let mut before = p.apply_uci_moves(&moves[..99]).unwrap();
+        let mut search = |depth| {
+            eval_minimax::<MaterialBalance>(
+                &mut before,
+                depth,
+                &mut DrawTable::default(),
+                &mut StopCheck::never(),
+            )
+            .eval
+            .score(0)
+        };
+        assert_eq!(search(0), 500);
+        assert_eq!(search(1), 0);
+    }
+
+    #[tokio::test]
+    async fn update_stream() {
+        use futures::StreamExt;
+
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            depth: Some(3),
+            ..Default::default()
+        };
+        let pos = Position::startingpos();
+        let (search, updates) =
+            AlphaBeta::search::<MaterialBalance, CaptureUciStream>(sigstop, pos, options, None);
+        CaptureUciStream::take();
+        search.await;
+        let out = CaptureUciStream::take();
+        // the stream ends with the search
+        let updates: Vec<SearchUpdate> = updates.collect().await;
+        let depths: Vec<usize> = updates.iter().map(|u| u.depth).collect();
+        assert_eq!(depths, [1, 2, 3]);
+        assert!(updates.windows(2).all(|u| u[0].nodes < u[1].nodes));
+        assert!(updates.iter().all(|u| !u.pv.moves().is_empty()));
+        // the info lines are the updates
+        for u in &updates {
+            assert!(out.contains(&format!("info {u}\n")), "{out:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn single_legal_move() {
+        // the rook and king leave Kh7 as the only move
+        let pos = Position::from_fen("7k/8/5K2/8/8/8/8/6R1", "b", "-", "-", "0", "1");
+        assert_eq!(pos.legal_move_count(), 1);
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            movetime: Some(std::time::Duration::from_secs(30)),
+            ..Default::default()
+        };
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(sigstop, pos, options, None)
+            .await;
+        let out = CaptureUciStream::take();
+        // a single iteration, the time allowed is left unused
+        assert!(
+            out.iter().any(|l| l.starts_with("info depth 1 ")),
+            "{out:?}"
+        );
+        assert!(
+            out.iter().all(|l| !l.starts_with("info depth 2 ")),
+            "{out:?}"
+        );
+        assert_eq!(out.last().unwrap(), "bestmove h8h7\n");
+
+        // analysing, the forced move still gets a real evaluation
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            analyse_mode: true,
+            depth: Some(3),
+            ..Default::default()
+        };
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(sigstop, pos, options, None)
+            .await;
+        let out = CaptureUciStream::take();
+        assert!(
+            out.iter().any(|l| l.starts_with("info depth 3 ")),
+            "{out:?}"
+        );
+        assert_eq!(out.last().unwrap(), "bestmove h8h7\n");
+    }
+
+    #[test]
+    fn mate_and_stalemate() {
+        use crate::eval::is_mate_score;
+
+        let node = |fen, turn, depth| {
+            let pos = Position::from_fen(fen, turn, "-", "-", "0", "1");
+            let (mut a, mut b) = (pos, pos);
+            let mut draws = DrawTable::default();
+            let full =
+                eval_minimax::<MaterialBalance>(&mut a, depth, &mut draws, &mut StopCheck::never());
+            let pruned = eval_alphabeta_full::<MaterialBalance>(
+                &mut b,
+                depth,
+                &mut draws,
+                &mut StopCheck::never(),
+            );
+            assert_eq!(full.eval.score(0), pruned.eval.score(0), "{fen}");
+            full
+        };
+        // back rank mate in one, seen once the replies are generated, then the mated position
+        let e = node("6k1/5ppp/8/8/8/8/5PPP/4R1K1", "w", 2);
+        assert!(is_mate_score(e.eval.score(0)) && e.eval.score(0) > 0);
+        assert_eq!(format!("{}", e.pv.moves()[0]), "e1e8");
+        let e = node("4R1k1/5ppp/8/8/8/8/5PPP/6K1", "b", 1);
+        assert!(is_mate_score(e.eval.score(0)) && e.eval.score(0) > 0);
+
+        // stalemate, a queen up is worth nothing
+        let e = node("7k/5Q2/6K1/8/8/8/8/8", "b", 1);
+        assert_eq!(e.eval.score(0), 0);
+        // Qf7 stalemates, any other queen move keeps the win
+        let e = node("7k/8/6K1/8/8/8/5Q2/8", "w", 2);
+        assert!(e.eval.score(0) > 0);
+        assert_ne!(format!("{}", e.pv.moves()[0]), "f2f7");
+    }
+
+    #[tokio::test]
+    async fn repetition_at_root() {
+        // white a queen up, the root being the third occurrence of the position
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"].repeat(2);
+        let start = Position::from_fen("1nb1kbn1/8/8/8/8/8/8/QNB1KBN1", "w", "-", "-", "0", "1");
+        let (pos, history) = start.apply_uci_moves_with_history(&moves).unwrap();
+        let (_sendstop, sigstop) = futures::channel::oneshot::channel();
+        let (send, recv) = futures::channel::oneshot::channel();
+        let options = SearchOptions {
+            depth: Some(2),
+            history,
+            ..Default::default()
+        };
+        CaptureUciStream::take();
+        MiniMaxMVP::infinite::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            pos,
+            options,
+            Some(send),
+        )
+        .await;
+        let out = CaptureUciStream::take();
+        let (best, e) = recv.await.unwrap();
+        assert!(best.is_some());
+        assert_eq!(e.score(0), 0);
+        let scores: Vec<&String> = out.iter().filter(|l| l.contains(" score ")).collect();
+        assert!(!scores.is_empty(), "{out:?}");
+        assert!(scores.iter().all(|l| l.contains(" score cp 0 ")), "{out:?}");
+        assert!(out.last().unwrap().starts_with("bestmove "), "{out:?}");
+    }
+
+    #[test]
+    fn draw_table_transposition() {
+        // king and knight against king, the same position reached by two move orders
+        let root = Position::from_fen("4k3/8/8/8/8/8/8/4K1N1", "w", "-", "-", "0", "1");
+        let mut draws = DrawTable::default();
+        for moves in [["e1d1", "e8d8", "g1f3"], ["g1f3", "e8d8", "e1d1"]] {
+            let mut p = root.apply_uci_moves(&moves).unwrap();
+            let e = eval_minimax::<MaterialBalance>(&mut p, 0, &mut draws, &mut StopCheck::never());
+            assert_eq!(e.eval.score(0), 0);
+        }
+        assert_eq!(draws.hits, 1);
+        assert_eq!(draws.keys.len(), 1);
+    }
+}
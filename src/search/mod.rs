@@ -1,15 +1,290 @@
+use std::{
+    fmt::Display,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
 use futures::channel;
 
-use crate::{eval::BasicEvaluation, position::Position, uci::UciOutputStream};
+use crate::{
+    eval::{BasicEvaluation, Eval, EvalState, MoveList},
+    position::{Move, Position, RepetitionHistory},
+    uci::UciOutputStream,
+};
 
 mod basic_minimax;
+pub mod ordering;
+pub mod time;
+
+// ShowInfo: which info lines a search sends
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ShowInfo {
+    // one line per completed depth
+    #[default]
+    Always,
+    // only the final one, right before bestmove
+    Final,
+}
+
+// parameters of a search, set by the UCI options
+#[derive(Clone)]
+pub struct SearchOptions {
+    // UCI_AnalyseMode: also report refutations of the inferior root moves
+    pub analyse_mode: bool,
+    // go depth: stop once this depth is completed
+    pub depth: Option<usize>,
+    // go nodes: stop once this many nodes are searched
+    pub nodes: Option<usize>,
+    pub show_info: ShowInfo,
+    // nodes searched between two polls of the stop signal
+    pub stop_interval: usize,
+    // time allowed for the move, shortened when the best move is settled early
+    pub movetime: Option<Duration>,
+    // positions of the game before the root, a third occurrence is a draw
+    pub history: RepetitionHistory,
+    // centipawns the side to move at the root gives up to avoid a draw
+    pub contempt: i32,
+    // receives an update per completed depth, for embedders not reading the info lines
+    pub updates: Option<channel::mpsc::UnboundedSender<SearchUpdate>>,
+}
+
+impl SearchOptions {
+    // bounded by depth, nodes or time, a search that would otherwise wait for stop
+    pub fn is_limited(&self) -> bool {
+        self.depth.is_some() || self.nodes.is_some() || self.movetime.is_some()
+    }
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            analyse_mode: false,
+            depth: None,
+            nodes: None,
+            show_info: ShowInfo::default(),
+            stop_interval: 2048,
+            movetime: None,
+            history: RepetitionHistory::default(),
+            contempt: 0,
+            updates: None,
+        }
+    }
+}
+
+// stop signal (or deadline) polled every few nodes, inside an iteration
+pub struct StopCheck<'a> {
+    sigstop: Option<&'a mut channel::oneshot::Receiver<()>>,
+    deadline: Option<Instant>,
+    max_nodes: Option<usize>,
+    interval: usize,
+    // shared with the threads helping the search, each counting its own nodes in it
+    nodes: Arc<AtomicU64>,
+    stopped: bool,
+}
+
+impl<'a> StopCheck<'a> {
+    pub fn new(sigstop: &'a mut channel::oneshot::Receiver<()>, interval: usize) -> Self {
+        Self {
+            sigstop: Some(sigstop),
+            deadline: None,
+            max_nodes: None,
+            interval: interval.max(1),
+            nodes: Arc::default(),
+            stopped: false,
+        }
+    }
+
+    // searches without stop signal, run to completion
+    pub fn never() -> Self {
+        Self {
+            sigstop: None,
+            deadline: None,
+            max_nodes: None,
+            interval: usize::MAX,
+            nodes: Arc::default(),
+            stopped: false,
+        }
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    // node budget, counted from the start of the search
+    pub fn set_node_limit(&mut self, max_nodes: Option<usize>) {
+        self.max_nodes = max_nodes;
+    }
+
+    // check the signal now, a dropped sender counts as a stop
+    pub fn poll(&mut self) -> bool {
+        if let Some(sigstop) = self.sigstop.as_mut() {
+            self.stopped |= !matches!(sigstop.try_recv(), Ok(None));
+        }
+        self.stopped |= self.deadline.is_some_and(|d| Instant::now() >= d);
+        self.stopped
+    }
+
+    // count a node, checking the signal once per interval
+    pub fn tick(&mut self) -> bool {
+        let nodes = self.nodes.fetch_add(1, Ordering::Relaxed) as usize + 1;
+        if !self.stopped && nodes.is_multiple_of(self.interval) {
+            self.poll();
+        }
+        // checked at every node, unlike the signal
+        self.stopped |= self.max_nodes.is_some_and(|max| nodes > max);
+        self.stopped
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.nodes.load(Ordering::Relaxed) as usize
+    }
+
+    pub fn counter(&self) -> Arc<AtomicU64> {
+        self.nodes.clone()
+    }
+
+    // nodes per second since start
+    pub fn nps(&self, start: Instant) -> u128 {
+        self.nodes() as u128 * 1_000_000 / start.elapsed().as_micros().max(1)
+    }
+}
+
+// final outcome of a search: the move to play (None without legal moves) and its evaluation
+pub type SearchResult = (Option<Move>, Eval);
+
+// progress of a search once a depth is completed, what an info line reports
+#[derive(Clone)]
+pub struct SearchUpdate {
+    pub depth: usize,
+    pub score: Eval,
+    pub pv: MoveList,
+    // searched since the start of the search, all depths included
+    pub nodes: usize,
+    pub time: Duration,
+    pub nps: u128,
+    // occupied entries per thousand of the eval table (UCI hashfull), None without one
+    pub hashfull: Option<usize>,
+}
+
+// fields of the info line in the order of the UCI spec: depth, score, time (ms), nodes, nps,
+// hashfull, then the pv
+impl Display for SearchUpdate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} time {} nodes {} nps {}",
+            self.score,
+            self.time.as_millis(),
+            self.nodes,
+            self.nps
+        )?;
+        if let Some(x) = self.hashfull {
+            write!(f, " hashfull {x}")?;
+        }
+        write!(f, " {}", self.pv)
+    }
+}
 
 pub trait Search {
-    fn infinite<T: BasicEvaluation, Out : UciOutputStream>(
+    fn infinite<T: BasicEvaluation, Out: UciOutputStream>(
         sigstop: channel::oneshot::Receiver<()>,
         pos: Position,
+        options: SearchOptions,
+        result: Option<channel::oneshot::Sender<SearchResult>>,
     ) -> impl std::future::Future<Output = ()> + Send;
+    // best line at exactly the given depth, without output nor stop signal
+    fn fixed_depth<T: BasicEvaluation>(pos: Position, depth: usize) -> EvalState;
+    // infinite, its progress also streamed: one update per completed depth, the stream ending
+    // with the search once the future completes
+    fn search<T: BasicEvaluation, Out: UciOutputStream>(
+        sigstop: channel::oneshot::Receiver<()>,
+        pos: Position,
+        mut options: SearchOptions,
+        result: Option<channel::oneshot::Sender<SearchResult>>,
+    ) -> (
+        impl std::future::Future<Output = ()> + Send,
+        channel::mpsc::UnboundedReceiver<SearchUpdate>,
+    ) {
+        let (updates, stream) = channel::mpsc::unbounded();
+        options.updates = Some(updates);
+        (
+            Self::infinite::<T, Out>(sigstop, pos, options, result),
+            stream,
+        )
+    }
     // TODO: add other
 }
 
 pub type SearchDefault = basic_minimax::MiniMaxMVP;
+
+// SearchAlgo: the Search implementation spawned by go
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SearchAlgo {
+    #[default]
+    Minimax,
+    AlphaBeta,
+}
+
+// a search run by the caller on its own task, see SearchAlgo::search
+pub type SearchFuture = std::pin::Pin<Box<dyn std::future::Future<Output = ()> + Send>>;
+
+impl SearchAlgo {
+    // option values, the default first
+    pub const NAMES: &[&str] = &["minimax", "alphabeta"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "minimax" => Some(Self::Minimax),
+            "alphabeta" => Some(Self::AlphaBeta),
+            _ => None,
+        }
+    }
+
+    pub fn spawn<T: BasicEvaluation + 'static, Out: UciOutputStream + 'static>(
+        self,
+        runtime: &tokio::runtime::Runtime,
+        sigstop: channel::oneshot::Receiver<()>,
+        pos: Position,
+        options: SearchOptions,
+        result: Option<channel::oneshot::Sender<SearchResult>>,
+    ) -> tokio::task::JoinHandle<()> {
+        match self {
+            Self::Minimax => runtime.spawn(basic_minimax::MiniMaxMVP::infinite::<T, Out>(
+                sigstop, pos, options, result,
+            )),
+            Self::AlphaBeta => runtime.spawn(basic_minimax::AlphaBeta::infinite::<T, Out>(
+                sigstop, pos, options, result,
+            )),
+        }
+    }
+
+    // the search spawn would run, with its stream of updates, for a caller awaiting it along
+    // with the stream
+    pub fn search<T: BasicEvaluation + 'static, Out: UciOutputStream + 'static>(
+        self,
+        sigstop: channel::oneshot::Receiver<()>,
+        pos: Position,
+        options: SearchOptions,
+        result: Option<channel::oneshot::Sender<SearchResult>>,
+    ) -> (SearchFuture, channel::mpsc::UnboundedReceiver<SearchUpdate>) {
+        match self {
+            Self::Minimax => {
+                let (search, updates) =
+                    basic_minimax::MiniMaxMVP::search::<T, Out>(sigstop, pos, options, result);
+                (Box::pin(search), updates)
+            }
+            Self::AlphaBeta => {
+                let (search, updates) =
+                    basic_minimax::AlphaBeta::search::<T, Out>(sigstop, pos, options, result);
+                (Box::pin(search), updates)
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! Move ordering
+//!
+//! Captures and promotions are tried before quiet moves, sorted by MVV-LVA (most valuable victim,
+//! least valuable attacker, a promotion counting as the material it adds), then moves matching a
+//! known mating pattern are boosted to the front:
+//! - Smothered mate: knight check against a king whose every neighbour holds one of its own pieces
+//! - Back-rank mate: rook or queen check along the back rank, the king being walled in by its own
+//!   pieces on the rank in front of it
+//!
+//! These are cheap occupancy tests, not mate proofs: the king may still capture the checker.
+use std::cmp::Reverse;
+
+use crate::prelude::*;
+use movegen::attacks;
+
+const SMOTHERED_BONUS: i32 = 200;
+const BACK_RANK_BONUS: i32 = 100;
+
+// legal moves, the most promising first
+pub fn ordered_moves(pos: &Position) -> Vec<Move> {
+    let (mut moves, mut quiet) = pos.staged_moves();
+    moves.sort_by_key(|m| Reverse(mvv_lva(pos, m)));
+    moves.append(&mut quiet);
+    // stable, so noisy moves stay ahead of quiet ones with the same bonus
+    moves.sort_by_key(|m| Reverse(mate_pattern_bonus(pos, m)));
+    moves
+}
+
+// material won by a capture or a promotion, the attacker value only breaking ties
+pub fn mvv_lva(pos: &Position, m: &Move) -> i32 {
+    let Move::Normal(x) = m else {
+        return 0;
+    };
+    let victim = match pos.pos().get((pos.turn().other(), x.dest.into())) {
+        Some(p) => p.value() as i32,
+        None if pos.is_capture(m) => Piece::Pawn.value() as i32, // en passant
+        None => 0,
+    };
+    let promoted = x
+        .promotion
+        .map_or(0, |p| (p.value() - Piece::Pawn.value()) as i32);
+    let attacker = match x.piece {
+        Piece::King => 0,
+        p => p.value() as i32,
+    };
+    10 * (victim + promoted) - attacker / 100
+}
+
+pub fn mate_pattern_bonus(pos: &Position, m: &Move) -> i32 {
+    let Move::Normal(x) = m else {
+        return 0;
+    };
+    let opp = pos.turn().other();
+    let Some(king) = pos.pos()[(opp, Piece::King)].into_iter().next() else {
+        return 0;
+    };
+    let dest: Bitboard<Square> = x.dest.into();
+    // squares around the king not taken by its own pieces
+    let escapes = attacks::generate_king(king) & !pos.occupied_by(opp);
+
+    match x.piece {
+        Piece::Knight
+            if attacks::generate_knights(dest.declass()) & king.declass()
+                != SpecialBB::Empty.declass()
+                && escapes == SpecialBB::Empty.declass() =>
+        {
+            SMOTHERED_BONUS
+        }
+        p if p.is_major() => {
+            let backrank = opp.backrank().declass();
+            let src: Bitboard<Square> = x.src.into();
+            let blockers = pos.occupied() & !src.declass();
+            let on_backrank = king.declass() & backrank != SpecialBB::Empty.declass()
+                && dest.declass() & backrank != SpecialBB::Empty.declass();
+            let checks = attacks::generate_rooks(dest.declass(), blockers) & king.declass()
+                != SpecialBB::Empty.declass();
+            match on_backrank && checks && escapes & !backrank == SpecialBB::Empty.declass() {
+                true => BACK_RANK_BONUS,
+                false => 0,
+            }
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smothered_check_first() {
+        // Nf7# ahead of the capture Nxh3
+        let p = Position::from_fen("6rk/6pp/8/6N1/8/7p/8/6K1", "w", "-", "-", "0", "1");
+        let moves = ordered_moves(&p);
+        assert_eq!(format!("{}", moves[0]), "g5f7");
+        assert_eq!(format!("{}", moves[1]), "g5h3");
+        assert_eq!(
+            moves.len(),
+            p.staged_moves().0.len() + p.staged_moves().1.len()
+        );
+    }
+
+    #[test]
+    fn promotion_capture_first() {
+        let p = Position::from_fen("3r3k/4P3/8/8/8/8/8/K7", "w", "-", "-", "0", "1");
+        let moves = ordered_moves(&p);
+        assert_eq!(format!("{}", moves[0]), "e7d8q");
+        // takes the rook and gains a queen for the pawn
+        assert_eq!(mvv_lva(&p, &moves[0]), 10 * (500 + 800) - 1);
+        assert_eq!(format!("{}", moves[1]), "e7d8r");
+        let push = moves.iter().find(|m| format!("{m}") == "e7e8q").unwrap();
+        assert_eq!(mvv_lva(&p, push), 10 * 800 - 1);
+    }
+
+    #[test]
+    fn back_rank_check_first() {
+        let p = Position::from_fen("6k1/5ppp/8/8/8/8/8/R5K1", "w", "-", "-", "0", "1");
+        assert_eq!(format!("{}", ordered_moves(&p)[0]), "a1a8");
+        // an escape square on the second rank removes the bonus
+        let p = Position::from_fen("6k1/5pp1/8/8/8/8/8/R5K1", "w", "-", "-", "0", "1");
+        assert!(
+            ordered_moves(&p)
+                .iter()
+                .all(|m| mate_pattern_bonus(&p, m) == 0)
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Time management
+//!
+//! Converts the clock state sent along with `go` into a time budget for the search.
+//! The move overhead accounts for the delay between the engine sending its move
+//! and the GUI stopping the clock (network, process scheduling...).
+//!
+//! The budget is then scaled along the search: a best move that stayed the same for several
+//! iterations, well ahead of the others, is played early ("easy move").
+use std::time::Duration;
+
+// expected number of moves left in the game when nothing else is known
+const MOVES_TO_GO: u32 = 30;
+// iterations in a row the best move must have been found by to be an easy move
+const EASY_MOVE_ITERATIONS: usize = 3;
+// lead in centipawns over the second best root move
+const EASY_MOVE_MARGIN: i32 = 200;
+// share of the budget spent on an easy move
+const EASY_MOVE_DIVISOR: u32 = 4;
+
+pub fn budget(time_left: Duration, increment: Duration, overhead: Duration) -> Duration {
+    // spend a fraction of the remaining time plus most of the increment,
+    // but never plan for more than what is left on the clock
+    (time_left / MOVES_TO_GO + increment * 3 / 4)
+        .min(time_left)
+        .saturating_sub(overhead)
+}
+
+// best root move of the last iterations, (from, to) squares
+#[derive(Default)]
+pub struct Stability {
+    best: Option<(u8, u8)>,
+    iterations: usize,
+    margin: i32,
+}
+
+impl Stability {
+    // record a completed iteration, margin being the lead of its best move over the second one
+    pub fn update(&mut self, best: Option<(u8, u8)>, margin: i32) {
+        match best == self.best {
+            true => self.iterations += 1,
+            false => {
+                self.best = best;
+                self.iterations = 1;
+            }
+        }
+        self.margin = margin;
+    }
+
+    pub fn is_easy(&self) -> bool {
+        self.iterations >= EASY_MOVE_ITERATIONS && self.margin >= EASY_MOVE_MARGIN
+    }
+
+    // time worth spending out of the budget
+    pub fn scale(&self, budget: Duration) -> Duration {
+        match self.is_easy() {
+            true => budget / EASY_MOVE_DIVISOR,
+            false => budget,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overhead_reduces_budget() {
+        let wtime = Duration::from_secs(60);
+        let inc = Duration::ZERO;
+        let small = budget(wtime, inc, Duration::from_millis(10));
+        let large = budget(wtime, inc, Duration::from_millis(500));
+        assert!(large < small);
+        assert_eq!(small - large, Duration::from_millis(490));
+    }
+
+    #[test]
+    fn budget_fits_on_clock() {
+        let overhead = Duration::from_millis(10);
+        let b = budget(Duration::from_millis(50), Duration::from_secs(2), overhead);
+        assert_eq!(b, Duration::from_millis(40));
+        let b = budget(Duration::from_millis(5), Duration::ZERO, overhead);
+        assert_eq!(b, Duration::ZERO);
+    }
+
+    #[test]
+    fn easy_move_scaling() {
+        let budget = Duration::from_secs(4);
+        let mut s = Stability::default();
+        for _ in 0..EASY_MOVE_ITERATIONS {
+            assert_eq!(s.scale(budget), budget);
+            s.update(Some((11, 27)), 900);
+        }
+        assert_eq!(s.scale(budget), Duration::from_secs(1));
+        // a close second best
+        s.update(Some((11, 27)), 50);
+        assert_eq!(s.scale(budget), budget);
+        // the best move changed
+        s.update(Some((12, 28)), 900);
+        assert!(!s.is_easy());
+    }
+}
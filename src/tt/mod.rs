@@ -14,6 +14,49 @@ impl<'a> PickMoreRelevant<'a> for PerftInfo {
     }
 }
 
+// search results, scores are stored relative to the node so that mates stay
+// correct when the position is reached again at another ply
+pub type EvalCache = Cache<EvalInfo, usize, Position>;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalInfo {
+    score: i32,
+    pub depth: u32,
+}
+impl EvalInfo {
+    // score is relative to the search root, the position being at the given ply
+    pub fn new(score: i32, ply: usize, depth: u32) -> Self {
+        let score = match is_mate_score(score) {
+            true => score.signum() * (score.abs() + ply as i32),
+            false => score,
+        };
+        Self { score, depth }
+    }
+    // score relative to the current search root, the position being at the given ply
+    pub fn score(&self, ply: usize) -> i32 {
+        match is_mate_score(self.score) {
+            true => self.score.signum() * (self.score.abs() - ply as i32),
+            false => self.score,
+        }
+    }
+}
+impl<'a> PickMoreRelevant<'a> for EvalInfo {
+    fn pick_more_relevant(x: &'a Self, y: &'a Self) -> &'a Self {
+        if x.depth > y.depth { x } else { y }
+    }
+}
+
+// pawn hash table, pawn structure score (centipawns, white positive)
+pub type PawnCache = Cache<PawnInfo, bb64, PawnStructure>;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PawnInfo {
+    pub cp: i32,
+}
+impl<'a> PickMoreRelevant<'a> for PawnInfo {
+    fn pick_more_relevant(_: &'a Self, y: &'a Self) -> &'a Self {
+        y
+    }
+}
+
 /** Transposition tables : store any position-related content.
  * Data is located in the heap. Size has to be a power of 2
  * TODO: Object will be designed for concurrent access.
@@ -86,25 +129,33 @@ impl<X: CopyMoreRelevant + PartialEq, S: PartialEq, I: Hashable<S> + PartialEq +
         }
     }
 
+    // occupied entries per thousand, sampled on the first thousand entries (UCI hashfull)
+    pub fn fill_permille(&self) -> usize {
+        let sample = &self.raw[..self.raw.len().min(1000)];
+        sample.iter().filter(|x| x.is_some()).count() * 1000 / sample.len()
+    }
+
     #[cfg(debug_assertions)]
     pub fn print_stats(&self) {
         let elements = self.mask + 1;
         let stack = std::mem::size_of::<Self>();
         let heap = self.raw.capacity() * (size_of::<X>() + size_of::<S>());
-        println!(
+        dprintln!(
             "Cache ({} elements - {} + {} Bytes (static+dynamic))",
-            elements, stack, heap
+            elements,
+            stack,
+            heap
         );
-        println!(
+        dprintln!(
             "\tUsage : {} ({}%)",
             self._items,
             self._items as f64 / elements as f64 * 100.
         );
-        println!(
+        dprintln!(
             "\tUpdates : {}%",
             self._updated as f64 / elements as f64 * 100.
         );
-        println!(
+        dprintln!(
             "\tCollisions : {}%",
             (self._replaced - self._updated) as f64 / elements as f64 * 100.
         );
@@ -144,9 +195,9 @@ impl<X: CopyMoreRelevant + PartialEq, S: PartialEq, Idx: Hashable<S> + PartialEq
                         {
                             let original_position = unsafe { self._positions[i].assume_init_ref() };
                             if original_position != index {
-                                println!("A collision went undetected");
-                                println!("original : {:?}", original_position);
-                                println!("current : {:?}", index);
+                                dprintln!("A collision went undetected");
+                                dprintln!("original : {:?}", original_position);
+                                dprintln!("current : {:?}", index);
                                 panic!();
                             }
                         };
@@ -236,3 +287,25 @@ fn transposition_tables() {
         "Cache::push failed"
     );
 }
+
+#[test]
+fn fill_permille() {
+    let entry = PerftInfo { nodes: 1, depth: 1 };
+    let mut t = PerftCache::new(16);
+    assert_eq!(t.fill_permille(), 0);
+    t.push(&Position::startingpos(), &entry);
+    assert_eq!(t.fill_permille(), 1000 / 16);
+    for m in Position::startingpos().legal_moves() {
+        let p = Position::startingpos().playmove(&format!("{m}"));
+        t.push(&p.unwrap().unwrap(), &entry);
+    }
+    let filled = t.raw.iter().filter(|x| x.is_some()).count();
+    assert_eq!(t.fill_permille(), filled * 1000 / 16);
+
+    // only the first thousand entries are looked at
+    let mut t = PerftCache::new(4096);
+    t.raw[1000] = Some(entry);
+    assert_eq!(t.fill_permille(), 0);
+    t.raw[999] = Some(entry);
+    assert_eq!(t.fill_permille(), 1);
+}
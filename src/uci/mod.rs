@@ -1,21 +1,35 @@
 use std::{
+    collections::HashMap,
     fmt::Display,
-    io::{Stdout, Write, stdin, stdout},
+    io::{Write, stdin, stdout},
     marker::PhantomData,
     ops::{Deref, DerefMut},
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use futures::channel::oneshot::{Sender, channel};
+use futures::{
+    StreamExt,
+    channel::{
+        mpsc::UnboundedReceiver,
+        oneshot::{Receiver, Sender, channel},
+    },
+};
+use log::warn;
 use tokio::task::JoinHandle;
 
-use crate::{PositionSpec, eval::MaterialBalance, position::Position, search::Search};
+use crate::{
+    PositionSpec,
+    eval::{EvalStyle, MaterialBalance, PawnStructureEval, PieceSquareEval},
+    player::Player,
+    position::{Move, Position, RepetitionHistory},
+    search::{SearchAlgo, SearchOptions, SearchResult, SearchUpdate, ShowInfo, time},
+};
 
 const BUILD_NAME: &str = env!("CARGO_PKG_NAME");
 const BUILD_VERSION: &str = env!("CARGO_PKG_VERSION");
 const BUILD_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
-// const BUILD_ABOUT: &str = "Simple rust chess engine that will get better";
+const BUILD_ABOUT: &str = "Simple rust chess engine that will get better";
 // if accessible, you know the engine is in one of these states
 
 pub struct UciOut<O: Write> {
@@ -25,10 +39,49 @@ pub struct UciOut<O: Write> {
 pub struct UciShell {
     // state will be locked during critical commands
     runtime: Arc<Mutex<tokio::runtime::Runtime>>,
-    worker: Arc<Mutex<Option<(tokio::task::JoinHandle<()>, Sender<()>)>>>,
-    position: Arc<Mutex<Position>>, // TODO add here internal configuration
+    worker: Arc<Mutex<Option<Worker>>>,
+    position: Arc<Mutex<Position>>,
+    // positions played before it, for repetitions
+    history: Arc<Mutex<RepetitionHistory>>,
+    config: Arc<Mutex<UciConfig>>,
+    // fulfilled by the next search with its bestmove, for library users
+    result: Arc<Mutex<Option<Sender<SearchResult>>>>,
+    // games addressed by id (non-standard extension), independent from the position above,
+    // each with the positions played before it
+    games: Arc<Mutex<HashMap<usize, (Position, RepetitionHistory)>>>,
+    game_workers: Arc<Mutex<HashMap<usize, Worker>>>,
+}
+
+type Worker = (tokio::task::JoinHandle<()>, Sender<()>);
+
+// engine configuration, edited through setoption
+pub struct UciConfig {
+    pub move_overhead: Duration,
+    pub analyse_mode: bool,
+    pub show_info: ShowInfo,
+    // search threads, only stored as the search is single threaded for now
+    pub threads: usize,
+    pub search_algo: SearchAlgo,
+    pub eval_style: EvalStyle,
+    // centipawns given up to avoid a draw
+    pub contempt: i32,
+}
+
+impl Default for UciConfig {
+    fn default() -> Self {
+        Self {
+            move_overhead: Duration::from_millis(10),
+            analyse_mode: false,
+            show_info: ShowInfo::Always,
+            threads: 1,
+            search_algo: SearchAlgo::default(),
+            eval_style: EvalStyle::default(),
+            contempt: 0,
+        }
+    }
 }
 
+// a response is written at once, so that the lines of the search task and of the shell never mix
 pub trait UciOutputStream: Send {
     fn send_response<T: Display>(r: T) -> Result<(), std::io::Error>;
     fn send_debug<T: Display>(_r: T) -> Result<(), std::io::Error>;
@@ -42,18 +95,46 @@ impl UciShell {
             runtime: Arc::new(Mutex::new(tokio::runtime::Runtime::new().unwrap())),
             worker: Arc::new(Mutex::new(None)),
             position: Arc::new(Mutex::new(Position::startingpos())),
+            history: Arc::new(Mutex::new(RepetitionHistory::default())),
+            config: Arc::new(Mutex::new(UciConfig::default())),
+            result: Arc::new(Mutex::new(None)),
+            games: Arc::new(Mutex::new(HashMap::new())),
+            game_workers: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    // the next go sends its final (bestmove, eval) on the returned channel, on top of printing it
+    pub fn next_result(&self) -> Receiver<SearchResult> {
+        let (send, recv) = channel();
+        *self.result.lock().unwrap() = Some(send);
+        recv
+    }
 }
 
 pub fn parse(line: String) -> Result<ParsedCommand, ()> {
     let mut parsed = line.split_whitespace();
     match parsed.nth(0) {
         None => Err(()), // no command passed TODO!
-        Some(x) => match x {
+        // only the command keyword is case insensitive, fen and moves are not
+        Some(x) => match x.to_ascii_lowercase().as_str() {
             "uci" => Ok(ParsedCommand::Uci),
             "isready" => Ok(ParsedCommand::IsReady),
             "d" => Ok(ParsedCommand::PrintBoard),
+            "evalline" => Ok(ParsedCommand::EvalLine(
+                parsed.map(str::to_string).collect(),
+            )),
+
+            // non-standard extension: `position id <n> ...`, `go id <n> ...` and `stop id <n>`
+            // address one of several independent games
+            "position" | "go" | "stop" if line.split_whitespace().nth(1) == Some("id") => {
+                let id = parsed
+                    .nth(1)
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .ok_or(())?;
+                let rest: Vec<&str> = parsed.collect();
+                let command = parse(format!("{x} {}", rest.join(" ")))?;
+                Ok(ParsedCommand::WithId(id, Box::new(command)))
+            }
 
             "position" => Ok(ParsedCommand::Position(
                 // parse fen | starting pos
@@ -88,25 +169,116 @@ pub fn parse(line: String) -> Result<ParsedCommand, ()> {
 
             "go" => Ok(ParsedCommand::Go(match parsed.nth(0) {
                 #[cfg(feature = "perft")]
-                Some("perft") => GoCommand::Perft(match parsed.nth(0) {
-                    Some(i) => {
-                        let i = i.parse::<usize>().expect("Should have been int");
-                        i
-                    }
-                    _ => return Err(()), // self.debug_msg("Missing depth");
-                }),
-                Some("infinite") => GoCommand::Infinite,
-                _ => todo!(),
+                Some("perft") => match parsed.next().and_then(|i| i.parse::<usize>().ok()) {
+                    Some(i) => GoCommand::Perft(i),
+                    None => return Err(()),
+                },
+                // without limits, searches until stopped
+                Some("infinite") | None => GoCommand::Infinite,
+                Some("depth") => match parsed.next().and_then(|d| d.parse::<usize>().ok()) {
+                    Some(d) => GoCommand::Depth(d),
+                    None => return Err(()),
+                },
+                Some("nodes") => match parsed.next().and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) => GoCommand::Nodes(n),
+                    None => return Err(()),
+                },
+                Some("movetime") => match parsed.next().and_then(|t| t.parse::<u64>().ok()) {
+                    Some(t) => GoCommand::MoveTime(Duration::from_millis(t)),
+                    None => return Err(()),
+                },
+                Some(first @ ("wtime" | "btime" | "winc" | "binc" | "movestogo")) => {
+                    parse_clock(std::iter::once(first).chain(parsed))?
+                }
+                _ => return Err(()),
             })),
 
+            // setoption name <id> [value <x>], both may contain spaces
+            "setoption" => {
+                let mut name: Vec<&str> = Vec::new();
+                let mut value: Option<Vec<&str>> = None;
+                if parsed.next() != Some("name") {
+                    return Err(());
+                }
+                for word in parsed {
+                    match (&mut value, word) {
+                        (None, "value") => value = Some(Vec::new()),
+                        (None, w) => name.push(w),
+                        (Some(v), w) => v.push(w),
+                    }
+                }
+                Ok(ParsedCommand::SetOption {
+                    name: name.join(" "),
+                    value: value.map(|v| v.join(" ")),
+                })
+            }
+
             "stop" => Ok(ParsedCommand::Stop),
             "quit" => Ok(ParsedCommand::Quit),
+            "ucinewgame" => Ok(ParsedCommand::UciNewGame),
 
             _ => Err(()), // return self.failed_parsing_behavior("unsupported command."),
         },
     }
 }
 
+// go wtime <x> btime <x> [winc <x>] [binc <x>], in milliseconds, missing values being 0
+fn parse_clock<'a>(mut args: impl Iterator<Item = &'a str>) -> Result<GoCommand, ()> {
+    let [mut wtime, mut btime, mut winc, mut binc] = [Duration::ZERO; 4];
+    while let Some(key) = args.next() {
+        let ms = args.next().and_then(|x| x.parse::<u64>().ok()).ok_or(())?;
+        let t = Duration::from_millis(ms);
+        match key {
+            "wtime" => wtime = t,
+            "btime" => btime = t,
+            "winc" => winc = t,
+            "binc" => binc = t,
+            // not used by the time management yet
+            "movestogo" => (),
+            _ => return Err(()),
+        }
+    }
+    Ok(GoCommand::Clock {
+        wtime,
+        btime,
+        winc,
+        binc,
+    })
+}
+
+// info and bestmove lines of the search of the game of the given id, from its updates and
+// its result; the last update stands for the final info line
+async fn report_game<Out: UciOutputStream>(
+    id: usize,
+    show_info: ShowInfo,
+    mut updates: UnboundedReceiver<SearchUpdate>,
+    result: Receiver<SearchResult>,
+) {
+    let mut last = None;
+    while let Some(update) = updates.next().await {
+        if show_info == ShowInfo::Always {
+            Out::send_response(UciResponse::Info(format!("id {id} {update}").as_str())).unwrap();
+        }
+        last = Some(update);
+    }
+    if let (ShowInfo::Final, Some(update)) = (show_info, last) {
+        Out::send_response(UciResponse::Info(format!("id {id} {update}").as_str())).unwrap();
+    }
+    // dropped by an aborted search, nothing to report
+    if let Ok((best, _)) = result.await {
+        Out::send_response(UciResponse::GameBestMove(id, best)).unwrap();
+    }
+}
+
+// interactive conveniences: blank lines are ignored, a lone `.` repeats the last command
+fn expand_line(line: &str, last: Option<&str>) -> Option<String> {
+    match line.trim() {
+        "" => None,
+        "." => last.map(str::to_string),
+        _ => Some(line.to_string()),
+    }
+}
+
 impl UciOutputStream for UciOut<std::io::Sink> {
     fn send_response<T: Display>(_r: T) -> Result<(), std::io::Error> {
         Ok(())
@@ -119,13 +291,15 @@ impl UciOutputStream for UciOut<std::io::Sink> {
 
 impl UciOutputStream for UciOut<std::io::Stdout> {
     fn send_response<T: Display>(r: T) -> Result<(), std::io::Error> {
-        let mut out_mut = stdout();
+        let mut out_mut = stdout().lock();
         /*if let UciResponse::Debug(_) = r {
             if self.debug == false {
                 return Ok(());
             }
         }*/
-        write!(out_mut, "{r}")
+        write!(out_mut, "{r}")?;
+        // GUIs read line by line, a bestmove must not wait in the buffer
+        out_mut.flush()
     }
 
     fn send_debug<T: Display>(r: T) -> Result<(), std::io::Error> {
@@ -148,21 +322,95 @@ impl UciOutputStream for NullUciStream {
     }
 }
 
+#[cfg(test)]
+thread_local! {
+    static CAPTURED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+// records every line sent on the current thread, for inspection in tests
+#[cfg(test)]
+pub struct CaptureUciStream {}
+#[cfg(test)]
+impl CaptureUciStream {
+    pub fn take() -> Vec<String> {
+        CAPTURED.with(|c| std::mem::take(&mut *c.borrow_mut()))
+    }
+}
+#[cfg(test)]
+impl UciOutputStream for CaptureUciStream {
+    fn send_response<T: Display>(r: T) -> Result<(), std::io::Error> {
+        CAPTURED.with(|c| c.borrow_mut().push(format!("{r}")));
+        Ok(())
+    }
+    fn send_debug<T: Display>(r: T) -> Result<(), std::io::Error> {
+        Self::send_response(r)
+    }
+}
+
 pub enum ParsedCommand {
     Uci,
     IsReady,
     Position(Position, Option<Vec<String>>),
     Go(GoCommand),
+    SetOption { name: String, value: Option<String> },
     Quit,
     Stop,
+    UciNewGame,
+    // command addressed to the game of this id
+    WithId(usize, Box<ParsedCommand>),
     // non standard ones :
     PrintBoard,
+    // static evaluation after each move of a line, played from the current position
+    EvalLine(Vec<String>),
 }
 
 pub enum GoCommand {
     #[cfg(feature = "perft")]
     Perft(usize),
     Infinite,
+    Depth(usize),
+    Nodes(usize),
+    Clock {
+        wtime: Duration,
+        btime: Duration,
+        winc: Duration,
+        binc: Duration,
+    },
+    MoveTime(Duration),
+}
+
+impl GoCommand {
+    // depth limit of the search, if any
+    fn depth(&self) -> Option<usize> {
+        match self {
+            GoCommand::Depth(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    // node budget of the search, if any
+    fn nodes(&self) -> Option<usize> {
+        match self {
+            GoCommand::Nodes(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    // time the search may spend on the move, for the player to move
+    fn movetime(&self, turn: Player, overhead: Duration) -> Option<Duration> {
+        match *self {
+            GoCommand::Clock {
+                wtime,
+                btime,
+                winc,
+                binc,
+            } => Some(match turn {
+                Player::White => time::budget(wtime, winc, overhead),
+                Player::Black => time::budget(btime, binc, overhead),
+            }),
+            GoCommand::MoveTime(t) => Some(t.saturating_sub(overhead)),
+            _ => None,
+        }
+    }
 }
 #[allow(unused)]
 pub enum UciOption {
@@ -177,7 +425,130 @@ pub enum UciOption {
     Check {
         default: bool,
     },
+    Combo {
+        default: &'static str,
+        vars: &'static [&'static str],
+    },
+}
+// setoption value, checked against the option definition
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParsedValue {
+    String(String),
+    Spin(usize),
+    Check(bool),
+    Combo(&'static str),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionError {
+    NotAnInteger { min: usize, max: usize },
+    NotABool,
+    UnknownVar(&'static [&'static str]),
+}
+
+impl Display for OptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptionError::NotAnInteger { min, max } => {
+                write!(f, "expects an integer between {min} and {max}")
+            }
+            OptionError::NotABool => write!(f, "expects true or false"),
+            OptionError::UnknownVar(vars) => write!(f, "expects one of {}", vars.join(" ")),
+        }
+    }
+}
+
+impl UciOption {
+    // spin values out of bounds are clamped, a GUI may not check them
+    pub fn validate(&self, value: &str) -> Result<ParsedValue, OptionError> {
+        match self {
+            UciOption::String { .. } => Ok(ParsedValue::String(value.to_string())),
+            UciOption::Spin { min, max, .. } => {
+                let n = value
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| OptionError::NotAnInteger {
+                        min: *min,
+                        max: *max,
+                    })?;
+                let clamped = n.clamp(*min as i64, *max as i64);
+                if clamped != n {
+                    warn!("Spin value {n} out of [{min}, {max}], clamped to {clamped}");
+                }
+                Ok(ParsedValue::Spin(clamped as usize))
+            }
+            UciOption::Check { .. } => match value.trim() {
+                "true" => Ok(ParsedValue::Check(true)),
+                "false" => Ok(ParsedValue::Check(false)),
+                _ => Err(OptionError::NotABool),
+            },
+            UciOption::Combo { vars, .. } => vars
+                .iter()
+                .find(|v| v.eq_ignore_ascii_case(value.trim()))
+                .map(|v| ParsedValue::Combo(v))
+                .ok_or(OptionError::UnknownVar(vars)),
+        }
+    }
 }
+
+// options sent on uci, the values received by setoption are checked against them
+fn uci_options() -> [(&'static str, UciOption); 8] {
+    [
+        (
+            "UCI_EngineAbout",
+            UciOption::String {
+                default: BUILD_ABOUT.to_string(),
+            },
+        ),
+        (
+            "Threads",
+            UciOption::Spin {
+                default: 1,
+                min: 1,
+                max: 1024,
+            },
+        ),
+        (
+            "Move Overhead",
+            UciOption::Spin {
+                default: 10,
+                min: 0,
+                max: 5000,
+            },
+        ),
+        ("UCI_AnalyseMode", UciOption::Check { default: false }),
+        (
+            "ShowInfo",
+            UciOption::Combo {
+                default: "always",
+                vars: &["always", "final"],
+            },
+        ),
+        (
+            "SearchAlgo",
+            UciOption::Combo {
+                default: SearchAlgo::NAMES[0],
+                vars: SearchAlgo::NAMES,
+            },
+        ),
+        (
+            "EvalStyle",
+            UciOption::Combo {
+                default: EvalStyle::NAMES[0],
+                vars: EvalStyle::NAMES,
+            },
+        ),
+        (
+            "Contempt",
+            UciOption::Spin {
+                default: 0,
+                min: 0,
+                max: 100,
+            },
+        ),
+    ]
+}
+
 impl Display for UciOption {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -186,6 +557,13 @@ impl Display for UciOption {
                 write!(f, "type spin default {default} min {min} max {max}")
             }
             UciOption::Check { default } => write!(f, "type check default {default}"),
+            UciOption::Combo { default, vars } => {
+                write!(f, "type combo default {default}")?;
+                for v in vars.iter() {
+                    write!(f, " var {v}")?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -198,6 +576,10 @@ pub enum UciResponse<'a> {
     Ok,
     Ready,
     Option { name: &'a str, o: UciOption },
+    Refutation(&'a [Move]),
+    BestMove(Option<Move>),
+    // bestmove of the game of the given id
+    GameBestMove(usize, Option<Move>),
 }
 
 impl<'a> Display for UciResponse<'a> {
@@ -210,8 +592,19 @@ impl<'a> Display for UciResponse<'a> {
             UciResponse::Raw(x) => writeln!(f, "{x}"),
             UciResponse::Id(x, y) => writeln!(f, "id {x} {y}"),
             UciResponse::Ok => writeln!(f, "uciok"),
-            UciResponse::Ready => writeln!(f, "uciready"),
+            UciResponse::Ready => writeln!(f, "readyok"),
             UciResponse::Option { name, o } => writeln!(f, "option name {name} {o}"),
+            UciResponse::Refutation(line) => {
+                write!(f, "info refutation")?;
+                for m in line.iter() {
+                    write!(f, " {m}")?;
+                }
+                writeln!(f)
+            }
+            UciResponse::BestMove(Some(m)) => writeln!(f, "bestmove {m}"),
+            UciResponse::BestMove(None) => writeln!(f, "bestmove (none)"),
+            UciResponse::GameBestMove(id, Some(m)) => writeln!(f, "bestmove id {id} {m}"),
+            UciResponse::GameBestMove(id, None) => writeln!(f, "bestmove id {id} (none)"),
         }
     }
 }
@@ -222,6 +615,33 @@ pub enum CommandResult {
 }
 
 impl UciShell {
+    // value already validated against the option
+    fn set_option(&self, id: &str, value: ParsedValue) {
+        let mut config = self.config.lock().unwrap();
+        match (id, value) {
+            ("Threads", ParsedValue::Spin(n)) => config.threads = n,
+            ("Move Overhead", ParsedValue::Spin(ms)) => {
+                config.move_overhead = Duration::from_millis(ms as u64)
+            }
+            ("UCI_AnalyseMode", ParsedValue::Check(b)) => config.analyse_mode = b,
+            ("ShowInfo", ParsedValue::Combo(v)) => {
+                config.show_info = match v {
+                    "final" => ShowInfo::Final,
+                    _ => ShowInfo::Always,
+                }
+            }
+            ("SearchAlgo", ParsedValue::Combo(v)) => {
+                config.search_algo = SearchAlgo::from_name(v).unwrap_or_default()
+            }
+            ("EvalStyle", ParsedValue::Combo(v)) => {
+                config.eval_style = EvalStyle::from_name(v).unwrap_or_default()
+            }
+            ("Contempt", ParsedValue::Spin(cp)) => config.contempt = cp as i32,
+            // informative only
+            _ => (),
+        }
+    }
+
     fn try_register(&self, j: JoinHandle<()>, sendstop: Sender<()>) -> Result<(), ()> {
         let mut lock = match self.worker.lock() {
             Ok(x) => x,
@@ -229,6 +649,8 @@ impl UciShell {
         };
         let channel = lock.deref_mut();
         match channel {
+            // a depth limited search may have ended on its own
+            Some((x, _)) if x.is_finished() => (),
             Some(_) => todo!("Cannot register"),
             None => (),
         };
@@ -236,12 +658,180 @@ impl UciShell {
         Ok(())
     }
 
+    fn spawn_search<Out: UciOutputStream + 'static>(
+        &self,
+        p: Position,
+        history: RepetitionHistory,
+        go: &GoCommand,
+    ) -> Worker {
+        let (sendstop, sigstop) = channel();
+        let (algo, style, options) = self.search_options(&p, history, go);
+        let result = self.result.lock().unwrap().take();
+        let lock = self.runtime.lock().unwrap();
+        let runtime = lock.deref();
+        let t = match style {
+            EvalStyle::Material => {
+                algo.spawn::<MaterialBalance, Out>(runtime, sigstop, p, options, result)
+            }
+            EvalStyle::Positional => {
+                algo.spawn::<PawnStructureEval, Out>(runtime, sigstop, p, options, result)
+            }
+            EvalStyle::Psqt => {
+                algo.spawn::<PieceSquareEval, Out>(runtime, sigstop, p, options, result)
+            }
+        };
+        (t, sendstop)
+    }
+
+    // search of the game of the given id: its lines carry the id and its result stays its own,
+    // the result channel being the one of the main game
+    fn spawn_game_search<Out: UciOutputStream + 'static>(
+        &self,
+        id: usize,
+        p: Position,
+        history: RepetitionHistory,
+        go: &GoCommand,
+    ) -> Worker {
+        let (sendstop, sigstop) = channel();
+        let (algo, style, options) = self.search_options(&p, history, go);
+        let show_info = options.show_info;
+        let (send, result) = channel();
+        // the lines of the search itself lack the id, they are sent again from its updates
+        type Silent = UciOut<std::io::Sink>;
+        let (search, updates) = match style {
+            EvalStyle::Material => {
+                algo.search::<MaterialBalance, Silent>(sigstop, p, options, Some(send))
+            }
+            EvalStyle::Positional => {
+                algo.search::<PawnStructureEval, Silent>(sigstop, p, options, Some(send))
+            }
+            EvalStyle::Psqt => {
+                algo.search::<PieceSquareEval, Silent>(sigstop, p, options, Some(send))
+            }
+        };
+        let report = report_game::<Out>(id, show_info, updates, result);
+        let t = self.runtime.lock().unwrap().spawn(async move {
+            futures::join!(search, report);
+        });
+        (t, sendstop)
+    }
+
+    // algorithm, evaluation and options of a search of p, from the configuration
+    fn search_options(
+        &self,
+        p: &Position,
+        history: RepetitionHistory,
+        go: &GoCommand,
+    ) -> (SearchAlgo, EvalStyle, SearchOptions) {
+        let config = self.config.lock().unwrap();
+        let options = SearchOptions {
+            analyse_mode: config.analyse_mode,
+            depth: go.depth(),
+            nodes: go.nodes(),
+            show_info: config.show_info,
+            movetime: go.movetime(p.turn(), config.move_overhead),
+            history,
+            contempt: config.contempt,
+            ..Default::default()
+        };
+        (config.search_algo, config.eval_style, options)
+    }
+
+    // signal the running search to stop, aborting it if it does not end in time
+    // returns whether there was a search to stop
+    async fn stop_worker<Out: UciOutputStream>(&self) -> bool {
+        let worker = match self.worker.lock() {
+            Ok(mut x) => x.take(),
+            Err(_) => todo!("Failed unlocking"),
+        };
+        match worker {
+            Some(w) => {
+                Self::stop_search::<Out>(w).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn stop_search<Out: UciOutputStream>((x, sendstop): Worker) {
+        // the search may have ended on its own, dropping the receiver
+        let _ = sendstop.send(());
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_millis(1000)) => {
+                Out::send_debug("Timeout reached, kill previous command").unwrap();
+            },
+            _ = async { loop { if x.is_finished() {break;} else {tokio::time::sleep(Duration::from_millis(10)).await} } } => {
+                Out::send_debug("Command ended peacefully").unwrap();
+            },
+        }
+        if !x.is_finished() {
+            x.abort();
+        }
+    }
+
+    // commands addressed to the game of the given id
+    async fn run_with_id<Out: UciOutputStream + 'static>(
+        &self,
+        id: usize,
+        c: ParsedCommand,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match c {
+            ParsedCommand::Position(p, m) => {
+                let game = match m {
+                    Some(mv) => p.apply_uci_moves_with_history(&mv),
+                    None => Ok((p, RepetitionHistory::default())),
+                };
+                match game {
+                    Ok(game) => _ = self.games.lock().unwrap().insert(id, game),
+                    Err(e) => Out::send_debug(UciResponse::Debug(format!("{e}").as_str()))?,
+                }
+            }
+            ParsedCommand::Go(
+                x @ (GoCommand::Infinite
+                | GoCommand::Depth(_)
+                | GoCommand::Nodes(_)
+                | GoCommand::Clock { .. }
+                | GoCommand::MoveTime(_)),
+            ) => {
+                let game = self.games.lock().unwrap().get(&id).cloned();
+                let mut workers = self.game_workers.lock().unwrap();
+                let running = workers.get(&id).is_some_and(|(t, _)| !t.is_finished());
+                match game {
+                    None => Out::send_debug(UciResponse::Debug(
+                        format!("No position for id {id}").as_str(),
+                    ))?,
+                    Some(_) if running => Out::send_debug(UciResponse::Debug(
+                        format!("Already searching for id {id}").as_str(),
+                    ))?,
+                    Some((p, history)) => {
+                        _ = workers.insert(id, self.spawn_game_search::<Out>(id, p, history, &x))
+                    }
+                }
+            }
+            ParsedCommand::Stop => {
+                let worker = self.game_workers.lock().unwrap().remove(&id);
+                if let Some(w) = worker {
+                    Self::stop_search::<Out>(w).await;
+                }
+            }
+            _ => Out::send_debug(UciResponse::Debug("Command not supported with an id"))?,
+        }
+        Ok(())
+    }
+
     // blocking until quit is recieved
     pub async fn run<Out: UciOutputStream + 'static>(&'static self) {
+        let mut last: Option<String> = None;
         loop {
             let mut line = String::new();
-            stdin().read_line(&mut line).unwrap();
-            let command = parse(line).unwrap();
+            if stdin().read_line(&mut line).unwrap() == 0 {
+                return; // end of input
+            }
+            let Some(line) = expand_line(&line, last.as_deref()) else {
+                continue;
+            };
+            let command = parse(line.clone()).unwrap();
+            last = Some(line);
             //.await.expect("Can't read line").unwrap();
 
             let res = self.runcommand::<Out>(command).await;
@@ -263,51 +853,43 @@ impl UciShell {
             _ => (),
         }
         match c {
-            ParsedCommand::Quit => return Ok(CommandResult::Finished(true)),
+            ParsedCommand::Quit => {
+                // do not leave a search running past the end of the shell
+                self.stop_worker::<Out>().await;
+                let workers: Vec<Worker> = self
+                    .game_workers
+                    .lock()
+                    .unwrap()
+                    .drain()
+                    .map(|(_, w)| w)
+                    .collect();
+                for w in workers {
+                    Self::stop_search::<Out>(w).await;
+                }
+                return Ok(CommandResult::Finished(true));
+            }
             ParsedCommand::Stop => {
-                let mut lock = match self.worker.lock() {
-                    Ok(x) => x,
-                    Err(_) => todo!("Failed unlocking"),
-                };
-                let channel = lock.deref_mut();
-                let channel = std::mem::replace(channel, None);
-                match channel {
-                    Some((x, sendstop)) => {
-                        sendstop.send(()).unwrap();
-                        tokio::select! {
-                            _ = tokio::time::sleep(Duration::from_millis(1000)) => {
-                                Out::send_debug("Timeout reached, kill previous command").unwrap();
-                            },
-                            _ = async { loop { if x.is_finished() {break;} else {tokio::time::sleep(Duration::from_millis(10)).await} } } => {
-                                Out::send_debug("Command ended peacefully").unwrap();
-                            },
-                        }
-                        if x.is_finished() == false {
-                            x.abort();
-                            return Ok(CommandResult::Finished(false));
-                        }
-                    }
-                    None => {
-                        Out::send_debug("No command to quit.").unwrap();
-                        ()
-                    }
+                if !self.stop_worker::<Out>().await {
+                    Out::send_debug("No command to quit.").unwrap();
                 }
             }
+            ParsedCommand::UciNewGame => {
+                // nothing from the previous game survives, its search included
+                self.stop_worker::<Out>().await;
+                *self.position.lock().unwrap() = Position::startingpos();
+                self.history.lock().unwrap().clear();
+                crate::eval::clear_caches();
+            }
             ParsedCommand::Uci => {
                 Out::send_response(UciResponse::Id(
                     "name",
                     format!("{} {}", BUILD_NAME, BUILD_VERSION),
                 ))?;
-                Out::send_response(UciResponse::Id("authors", format!("{}", BUILD_AUTHORS)))?;
-                // TODO: self.send_response(UciResponse:: &format!("option name UCI_EngineAbout {}", BUILD_ABOUT));
-                Out::send_response(UciResponse::Option {
-                    name: "Threads",
-                    o: UciOption::Spin {
-                        default: 1,
-                        min: 1,
-                        max: 1024,
-                    },
-                })?;
+                // the uci protocol names it author, even with several of them
+                Out::send_response(UciResponse::Id("author", BUILD_AUTHORS.to_string()))?;
+                for (name, o) in uci_options() {
+                    Out::send_response(UciResponse::Option { name, o })?;
+                }
 
                 Out::send_response(UciResponse::Ok)?;
             }
@@ -317,57 +899,104 @@ impl UciShell {
                 Out::send_response(UciResponse::Ready)?;
             }
 
+            ParsedCommand::SetOption { name, value } => {
+                let options = uci_options();
+                // option names are case insensitive
+                match options
+                    .iter()
+                    .find(|(id, _)| id.eq_ignore_ascii_case(&name))
+                {
+                    Some((id, option)) => {
+                        match option.validate(value.as_deref().unwrap_or_default()) {
+                            Ok(value) => self.set_option(id, value),
+                            // a GUI sending a bad value should hear about it, even in release
+                            Err(e) => Out::send_response(UciResponse::Info(
+                                format!("string {id} {e}").as_str(),
+                            ))?,
+                        }
+                    }
+                    None => Out::send_debug(UciResponse::Debug(
+                        format!("Unknown option {name}").as_str(),
+                    ))?,
+                }
+            }
+
+            ParsedCommand::WithId(id, c) => self.run_with_id::<Out>(id, *c).await?,
+
             ParsedCommand::PrintBoard => {
-                self.position
-                    .lock()
-                    .unwrap()
-                    .pretty_print::<UciOut<Stdout>>();
+                let history = self.history.lock().unwrap();
+                self.position.lock().unwrap().pretty_print::<Out>(&history);
+            }
+
+            ParsedCommand::EvalLine(moves) => {
+                let mut p = *self.position.lock().unwrap();
+                for m in moves {
+                    p = match p.apply_uci_moves(&[&m]) {
+                        Ok(p) => p,
+                        Err(e) => {
+                            Out::send_debug(UciResponse::Debug(format!("{e}").as_str()))?;
+                            break;
+                        }
+                    };
+                    let score = self.config.lock().unwrap().eval_style.eval(&p).score(0);
+                    Out::send_response(UciResponse::Info(
+                        format!("string evalline {m} score cp {score}").as_str(),
+                    ))?;
+                }
             }
 
             ParsedCommand::Position(p, m) => {
                 // parse fen | starting pos
-                let mut lock = self.position.lock();
-                let pos = lock.as_mut().unwrap();
-                pos.clone_from(&p);
-                match m {
-                    Some(mv) => {
-                        for m in mv {
-                            match pos.playmove(&m) {
-                                Err(()) => {
-                                    panic!("position was illegal to begin with");
-                                }
-                                Ok(None) => {
-                                    panic!("did not manage to play move");
-                                }
-                                Ok(Some(p)) => {
-                                    pos.clone_from(&p);
-                                } //pos.stack_simpl(&m),
-                            }
-                        }
+                let p = match m {
+                    Some(mv) => p.apply_uci_moves_with_history(&mv),
+                    None => Ok((p, RepetitionHistory::default())),
+                };
+                match p {
+                    Ok((p, history)) => {
+                        self.position.lock().unwrap().clone_from(&p);
+                        *self.history.lock().unwrap() = history;
                     }
-                    None => (),
+                    // keep the previous position rather than a partially applied move list
+                    Err(e) => Out::send_debug(UciResponse::Debug(format!("{e}").as_str()))?,
                 }
             }
 
             ParsedCommand::Go(x) => match x {
                 #[cfg(feature = "perft")]
                 GoCommand::Perft(i) => {
-                    let c = self.position.lock().unwrap().perft_top::<Out>(i);
+                    let start = Instant::now();
+                    // the root moves are shared among the search threads
+                    let threads = self.config.lock().unwrap().threads;
+                    let c = self
+                        .position
+                        .lock()
+                        .unwrap()
+                        .perft_top_parallel::<Out>(i, threads);
+                    let elapsed = start.elapsed();
+                    let nps = (c as f64 / elapsed.as_secs_f64().max(1e-9)) as u64;
+                    Out::send_response(UciResponse::Info(
+                        format!(
+                            "string perft({i}) = {c} in {} ms ({nps} nps)",
+                            elapsed.as_millis()
+                        )
+                        .as_str(),
+                    ))?;
                     Out::send_response(UciResponse::Raw(""))?;
                     Out::send_response(UciResponse::Raw(
                         format!("Nodes searched: {}", c).as_str(),
                     ))?;
                     Out::send_response(UciResponse::Raw(""))?;
                 }
-                GoCommand::Infinite => {
-                    let (sendstop, sigstop) = channel();
-                    let p = self.position.lock().unwrap().clone();
-                    let lock = self.runtime.lock().unwrap();
-                    let runtime = lock.deref();
-                    let t = runtime.spawn(crate::search::SearchDefault::infinite::<
-                        MaterialBalance,
-                        Out,
-                    >(sigstop, p));
+                GoCommand::Infinite
+                | GoCommand::Depth(_)
+                | GoCommand::Nodes(_)
+                | GoCommand::Clock { .. }
+                | GoCommand::MoveTime(_) => {
+                    // the previous search sends its bestmove before this one starts talking
+                    self.stop_worker::<Out>().await;
+                    let p = *self.position.lock().unwrap();
+                    let history = self.history.lock().unwrap().clone();
+                    let (t, sendstop) = self.spawn_search::<Out>(p, history, &x);
                     self.try_register(t, sendstop).unwrap();
                 }
             },
@@ -375,3 +1004,647 @@ impl UciShell {
         return Ok(CommandResult::Finished(false));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::BasicEvaluation;
+
+    fn shell() -> &'static UciShell {
+        Box::leak(Box::new(UciShell::new()))
+    }
+
+    async fn run(shell: &'static UciShell, command: &str) -> Vec<String> {
+        CaptureUciStream::take();
+        shell
+            .runcommand::<CaptureUciStream>(parse(command.to_string()).unwrap())
+            .await
+            .unwrap();
+        CaptureUciStream::take()
+    }
+
+    #[cfg(feature = "perft")]
+    #[tokio::test]
+    async fn perft_timing() {
+        let out = run(shell(), "go perft 3").await;
+        assert!(
+            out.contains(&"Nodes searched: 8902\n".to_string()),
+            "{out:?}"
+        );
+        let timing = out
+            .iter()
+            .find(|l| l.starts_with("info string perft(3) = 8902 in "))
+            .expect("perft timing line");
+        assert!(timing.trim_end().ends_with(" nps)"), "{timing}");
+    }
+
+    #[test]
+    fn parse_case_insensitive() {
+        assert!(matches!(parse("uci".to_string()), Ok(ParsedCommand::Uci)));
+        assert!(matches!(parse("UCI".to_string()), Ok(ParsedCommand::Uci)));
+        assert!(matches!(
+            parse("Position startpos moves e2e4".to_string()),
+            Ok(ParsedCommand::Position(_, Some(_)))
+        ));
+    }
+
+    #[test]
+    fn blank_and_repeat_lines() {
+        assert_eq!(expand_line("\n", Some("d")), None);
+        assert_eq!(expand_line("  \t\n", None), None);
+        assert_eq!(expand_line(".\n", None), None);
+        assert_eq!(
+            expand_line(".\n", Some("go depth 2\n")).as_deref(),
+            Some("go depth 2\n")
+        );
+        assert!(matches!(
+            parse(expand_line(".", Some("isready")).unwrap()),
+            Ok(ParsedCommand::IsReady)
+        ));
+        assert_eq!(expand_line("uci\n", Some("d")).as_deref(), Some("uci\n"));
+    }
+
+    #[tokio::test]
+    async fn uci_handshake() {
+        let out = run(shell(), "uci").await;
+        assert!(out.iter().any(|l| l.starts_with("id name ")), "{out:?}");
+        assert!(out.iter().any(|l| l.starts_with("id author ")), "{out:?}");
+        assert!(
+            out.iter()
+                .any(|l| l.starts_with("option name UCI_EngineAbout type string default ")),
+            "{out:?}"
+        );
+        assert_eq!(out.last().map(String::as_str), Some("uciok\n"));
+    }
+
+    // collects the lines of every thread, searches included, for the scripted session only
+    static SESSION: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    struct SessionStream {}
+    impl UciOutputStream for SessionStream {
+        fn send_response<T: Display>(r: T) -> Result<(), std::io::Error> {
+            SESSION.lock().unwrap().push(format!("{r}"));
+            Ok(())
+        }
+        fn send_debug<T: Display>(_r: T) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+    }
+
+    // feed a script to the shell as the run loop would, up to quit
+    // returns the output and the lines that could not be parsed
+    async fn session<'a>(script: &[&'a str]) -> (Vec<String>, Vec<&'a str>) {
+        let shell = shell();
+        let mut unsupported = Vec::new();
+        for &line in script {
+            let Some(expanded) = expand_line(line, None) else {
+                continue;
+            };
+            let Ok(command) = parse(expanded) else {
+                unsupported.push(line);
+                continue;
+            };
+            // like a GUI, wait for the bestmove before sending anything else
+            let search = matches!(command, ParsedCommand::Go(GoCommand::Depth(_)))
+                .then(|| shell.next_result());
+            match shell.runcommand::<SessionStream>(command).await.unwrap() {
+                CommandResult::Finished(true) => break,
+                CommandResult::Finished(false) => (),
+                CommandResult::Pending(h) => assert!(!h.await.unwrap()),
+            }
+            if let Some(result) = search {
+                result.await.unwrap();
+            }
+        }
+        (std::mem::take(&mut *SESSION.lock().unwrap()), unsupported)
+    }
+
+    #[tokio::test]
+    async fn scripted_session() {
+        let (out, unsupported) = session(&[
+            "uci",
+            "isready",
+            "ucinewgame",
+            "position startpos moves e2e4",
+            "go depth 2",
+            "quit",
+        ])
+        .await;
+        assert!(unsupported.is_empty(), "{unsupported:?}");
+
+        let line = |l: &str| out.iter().position(|x| x == l);
+        let uciok = line("uciok\n").expect("uciok");
+        let readyok = line("readyok\n").expect("readyok");
+        assert!(uciok < readyok, "{out:?}");
+        let bestmove = out
+            .iter()
+            .rposition(|l| l.starts_with("bestmove "))
+            .expect("bestmove");
+        assert!(readyok < bestmove, "{out:?}");
+        // a black move, answering e2e4
+        let best = out[bestmove].trim_end().trim_start_matches("bestmove ");
+        let p = Position::from_startpos_moves(&["e2e4"]).unwrap();
+        assert!(p.playmove(best).unwrap().is_some(), "{best}");
+    }
+
+    #[test]
+    fn parse_time_controls() {
+        let Ok(ParsedCommand::Go(clock)) =
+            parse("go wtime 300000 btime 200000 winc 2000 binc 1000".to_string())
+        else {
+            panic!("clock not parsed");
+        };
+        let overhead = Duration::from_millis(10);
+        assert_eq!(
+            clock.movetime(Player::White, overhead),
+            Some(time::budget(
+                Duration::from_secs(300),
+                Duration::from_secs(2),
+                overhead
+            ))
+        );
+        assert_eq!(
+            clock.movetime(Player::Black, overhead),
+            Some(time::budget(
+                Duration::from_secs(200),
+                Duration::from_secs(1),
+                overhead
+            ))
+        );
+        // missing increments
+        let Ok(ParsedCommand::Go(clock)) = parse("go btime 3000 wtime 6000".to_string()) else {
+            panic!("clock not parsed");
+        };
+        assert_eq!(
+            clock.movetime(Player::White, Duration::ZERO),
+            Some(time::budget(
+                Duration::from_secs(6),
+                Duration::ZERO,
+                Duration::ZERO
+            ))
+        );
+        let Ok(ParsedCommand::Go(x)) = parse("go movetime 5000".to_string()) else {
+            panic!("movetime not parsed");
+        };
+        assert_eq!(
+            x.movetime(Player::Black, overhead),
+            Some(Duration::from_millis(4990))
+        );
+        assert!(parse("go wtime".to_string()).is_err());
+        assert!(parse("go movetime soon".to_string()).is_err());
+    }
+
+    #[tokio::test]
+    async fn short_movetime_still_moves() {
+        let shell = shell();
+        let result = shell.next_result();
+        shell
+            .runcommand::<NullUciStream>(parse("go movetime 1".to_string()).unwrap())
+            .await
+            .unwrap();
+        // no stop sent, the search ends on its own
+        let (best, _) = result.await.unwrap();
+        assert!(best.is_some());
+    }
+
+    #[tokio::test]
+    async fn setoption_show_info() {
+        let shell = shell();
+        let out = run(shell, "uci").await;
+        assert!(
+            out.contains(
+                &"option name ShowInfo type combo default always var always var final\n"
+                    .to_string()
+            ),
+            "{out:?}"
+        );
+        run(shell, "setoption name ShowInfo value final").await;
+        assert_eq!(shell.config.lock().unwrap().show_info, ShowInfo::Final);
+        run(shell, "setoption name ShowInfo value sometimes").await;
+        assert_eq!(shell.config.lock().unwrap().show_info, ShowInfo::Final);
+    }
+
+    #[tokio::test]
+    async fn game_registry() {
+        let shell = shell();
+        run(shell, "position id 1 startpos moves e2e4").await;
+        run(shell, "position id 2 startpos moves d2d4 d7d5").await;
+        run(shell, "position startpos moves g1f3").await;
+        {
+            let games = shell.games.lock().unwrap();
+            assert!(games[&1].0 == Position::from_startpos_moves(&["e2e4"]).unwrap());
+            assert!(games[&2].0 == Position::from_startpos_moves(&["d2d4", "d7d5"]).unwrap());
+        }
+        // each game keeps the positions played before its own
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+        run(
+            shell,
+            &format!("position id 3 startpos moves {shuffle} {shuffle}"),
+        )
+        .await;
+        {
+            let games = shell.games.lock().unwrap();
+            let (p, history) = &games[&3];
+            assert!(history.is_threefold(p));
+            assert!(!games[&1].1.is_threefold(&games[&1].0));
+        }
+        let default = Position::from_startpos_moves(&["g1f3"]).unwrap();
+        assert!(*shell.position.lock().unwrap() == default);
+
+        // searches of both games at once
+        run(shell, "go id 1 infinite").await;
+        run(shell, "go id 2 infinite").await;
+        assert_eq!(shell.game_workers.lock().unwrap().len(), 2);
+        run(shell, "stop id 1").await;
+        assert!(shell.game_workers.lock().unwrap().contains_key(&2));
+        run(shell, "quit").await;
+        assert!(shell.game_workers.lock().unwrap().is_empty());
+
+        let out = run(shell, "go id 4 infinite").await;
+        assert!(
+            out.iter().any(|l| l.contains("No position for id 4")),
+            "{out:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn game_search_lines_carry_the_id() {
+        let (_sendstop, sigstop) = channel();
+        let (send, result) = channel();
+        let options = SearchOptions {
+            depth: Some(2),
+            ..Default::default()
+        };
+        let (search, updates) = SearchAlgo::AlphaBeta.search::<MaterialBalance, CaptureUciStream>(
+            sigstop,
+            Position::startingpos(),
+            options,
+            Some(send),
+        );
+        CaptureUciStream::take();
+        search.await;
+        // only the lines of the game are left
+        CaptureUciStream::take();
+        report_game::<CaptureUciStream>(3, ShowInfo::Always, updates, result).await;
+        let out = CaptureUciStream::take();
+        assert_eq!(out.len(), 3, "{out:?}");
+        assert!(
+            out[..2].iter().all(|l| l.starts_with("info id 3 ")),
+            "{out:?}"
+        );
+        assert!(out[2].starts_with("bestmove id 3 "), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn setoption_search_algo() {
+        let shell = shell();
+        let out = run(shell, "uci").await;
+        assert!(
+            out.contains(
+                &"option name SearchAlgo type combo default minimax var minimax var alphabeta\n"
+                    .to_string()
+            ),
+            "{out:?}"
+        );
+        let out = run(shell, "setoption name SearchAlgo value quantum").await;
+        assert_eq!(
+            out,
+            ["info string SearchAlgo expects one of minimax alphabeta\n"]
+        );
+        for name in SearchAlgo::NAMES {
+            run(shell, &format!("setoption name SearchAlgo value {name}")).await;
+            let algo = shell.config.lock().unwrap().search_algo;
+            assert_eq!(Some(algo), SearchAlgo::from_name(name));
+            let result = shell.next_result();
+            run(shell, "go depth 2").await;
+            let (best, _) = result.await.unwrap();
+            assert!(best.is_some(), "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn setoption_threads() {
+        let shell = shell();
+        assert_eq!(shell.config.lock().unwrap().threads, 1);
+        run(shell, "setoption name Threads value 4").await;
+        assert_eq!(shell.config.lock().unwrap().threads, 4);
+        let out = run(shell, "setoption name Threads value four").await;
+        assert_eq!(
+            out,
+            ["info string Threads expects an integer between 1 and 1024\n"]
+        );
+        assert_eq!(shell.config.lock().unwrap().threads, 4);
+        // out of bounds values are clamped
+        for (value, clamped) in [("2048", 1024), ("0", 1), ("-3", 1)] {
+            run(shell, &format!("setoption name Threads value {value}")).await;
+            assert_eq!(shell.config.lock().unwrap().threads, clamped);
+        }
+        // unknown options are only reported in debug
+        let out = run(shell, "setoption name Hash value 64").await;
+        assert_eq!(out, ["info string \"Unknown option Hash\"\n"]);
+    }
+
+    #[test]
+    fn validate_option_values() {
+        let spin = UciOption::Spin {
+            default: 10,
+            min: 0,
+            max: 5000,
+        };
+        assert_eq!(spin.validate("250"), Ok(ParsedValue::Spin(250)));
+        assert_eq!(spin.validate("9999"), Ok(ParsedValue::Spin(5000)));
+        assert_eq!(
+            spin.validate("ten"),
+            Err(OptionError::NotAnInteger { min: 0, max: 5000 })
+        );
+        let check = UciOption::Check { default: false };
+        assert_eq!(check.validate("true"), Ok(ParsedValue::Check(true)));
+        assert_eq!(check.validate("yes"), Err(OptionError::NotABool));
+        let combo = UciOption::Combo {
+            default: "always",
+            vars: &["always", "final"],
+        };
+        assert_eq!(combo.validate("Final"), Ok(ParsedValue::Combo("final")));
+        assert!(combo.validate("").is_err());
+    }
+
+    #[tokio::test]
+    async fn setoption_move_overhead() {
+        let shell = shell();
+        assert_eq!(
+            shell.config.lock().unwrap().move_overhead,
+            Duration::from_millis(10)
+        );
+        run(shell, "setoption name Move Overhead value 250").await;
+        assert_eq!(
+            shell.config.lock().unwrap().move_overhead,
+            Duration::from_millis(250)
+        );
+    }
+
+    #[tokio::test]
+    async fn go_depth_result_channel() {
+        let shell = shell();
+        let result = shell.next_result();
+        shell
+            .runcommand::<UciOut<std::io::Sink>>(parse("go depth 2".to_string()).unwrap())
+            .await
+            .unwrap();
+        let (best, _eval) = result.await.unwrap();
+        assert!(best.is_some());
+        // the channel is only fulfilled once
+        assert!(shell.result.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn parse_go_limits() {
+        for (line, depth, nodes) in [
+            ("go depth 8", Some(8), None),
+            ("go depth 0", Some(0), None),
+            ("go nodes 1000000", None, Some(1000000)),
+            ("go", None, None),
+        ] {
+            let Ok(ParsedCommand::Go(go)) = parse(line.to_string()) else {
+                panic!("{line} not parsed");
+            };
+            assert_eq!((go.depth(), go.nodes()), (depth, nodes), "{line}");
+        }
+        for line in [
+            "go depth -1",
+            "go depth eight",
+            "go nodes",
+            "go nodes -5",
+            "go fast",
+        ] {
+            assert!(parse(line.to_string()).is_err(), "{line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn go_depth_zero_and_nodes() {
+        let shell = shell();
+        for line in ["go depth 0", "go nodes 500"] {
+            let result = shell.next_result();
+            shell
+                .runcommand::<UciOut<std::io::Sink>>(parse(line.to_string()).unwrap())
+                .await
+                .unwrap();
+            let (best, _eval) = result.await.unwrap();
+            assert!(best.is_some(), "{line}");
+        }
+    }
+
+    #[tokio::test]
+    async fn quit_stops_search() {
+        let shell = shell();
+        let mut result = shell.next_result();
+        shell
+            .runcommand::<UciOut<std::io::Sink>>(parse("go infinite".to_string()).unwrap())
+            .await
+            .unwrap();
+        let quit = shell
+            .runcommand::<UciOut<std::io::Sink>>(parse("quit".to_string()).unwrap())
+            .await
+            .unwrap();
+        assert!(matches!(quit, CommandResult::Finished(true)));
+        assert!(shell.worker.lock().unwrap().is_none());
+        // the search ended and reported its move
+        assert!(result.try_recv().unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn fen_then_moves() {
+        let shell = shell();
+        run(
+            shell,
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 moves e2e4",
+        )
+        .await;
+        let expected = Position::from_startpos_moves(&["e2e4"]).unwrap();
+        assert!(*shell.position.lock().unwrap() == expected);
+
+        // counters omitted, as some GUIs do
+        run(shell, "position startpos").await;
+        run(
+            shell,
+            "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - moves e2e4",
+        )
+        .await;
+        assert!(*shell.position.lock().unwrap() == expected);
+    }
+
+    #[tokio::test]
+    async fn print_board_status() {
+        let shell = shell();
+        let out = run(shell, "d").await;
+        assert!(out.iter().any(|l| l.contains("Status: ongoing")), "{out:?}");
+
+        // fool's mate
+        run(
+            shell,
+            "position fen rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+        )
+        .await;
+        let out = run(shell, "d").await;
+        assert!(
+            out.iter()
+                .any(|l| l.contains("Status: checkmate (Black wins)")),
+            "{out:?}"
+        );
+
+        // the history of the game is taken into account
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+        run(
+            shell,
+            &format!("position startpos moves {shuffle} {shuffle}"),
+        )
+        .await;
+        let out = run(shell, "d").await;
+        assert!(
+            out.iter()
+                .any(|l| l.contains("Status: draw by threefold repetition")),
+            "{out:?}"
+        );
+        // the kings coming back lost the castling rights, only two occurrences
+        let kings = "e1e2 e8e7 e2e1 e7e8";
+        run(
+            shell,
+            &format!("position startpos moves e2e4 e7e5 {kings} {kings}"),
+        )
+        .await;
+        let out = run(shell, "d").await;
+        assert!(out.iter().any(|l| l.contains("Status: ongoing")), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn position_keeps_history() {
+        let shell = shell();
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+        run(
+            shell,
+            &format!("position startpos moves {shuffle} {shuffle}"),
+        )
+        .await;
+        let p = *shell.position.lock().unwrap();
+        assert!(shell.history.lock().unwrap().is_threefold(&p));
+        run(shell, &format!("position startpos moves {shuffle}")).await;
+        assert!(!shell.history.lock().unwrap().is_threefold(&p));
+        run(shell, "ucinewgame").await;
+        run(shell, "position startpos").await;
+        assert!(!shell.history.lock().unwrap().is_threefold(&p));
+    }
+
+    #[tokio::test]
+    async fn new_game_resets_position() {
+        let shell = shell();
+        let start = run(shell, "d").await;
+        run(shell, "position startpos moves e2e4 e7e5 g1f3").await;
+        run(shell, "go infinite").await;
+        run(shell, "ucinewgame").await;
+        assert!(shell.worker.lock().unwrap().is_none());
+        assert_eq!(run(shell, "d").await, start);
+    }
+
+    #[tokio::test]
+    async fn setoption_eval_style() {
+        let shell = shell();
+        let out = run(shell, "uci").await;
+        let option = "option name EvalStyle type combo default material var material var positional var psqt\n";
+        assert!(out.contains(&option.to_string()), "{out:?}");
+        // doubled isolated pawns: the styles disagree
+        let fen = "4k3/8/8/8/8/2P5/2P5/4K3 w - - 0 1";
+        run(shell, &format!("position fen {fen}")).await;
+        for name in EvalStyle::NAMES {
+            run(shell, &format!("setoption name EvalStyle value {name}")).await;
+            let style = shell.config.lock().unwrap().eval_style;
+            assert_eq!(Some(style), EvalStyle::from_name(name));
+            let result = shell.next_result();
+            run(shell, "go depth 2").await;
+            let (best, _) = result.await.unwrap();
+            assert!(best.is_some(), "{name}");
+        }
+
+        let p = Position::extract_fen(&mut fen.split_whitespace()).unwrap();
+        let material = EvalStyle::Material.eval(&p).score(0);
+        assert_eq!(material, MaterialBalance::eval(&p).score(0));
+        assert_eq!(material, 200);
+        assert!(EvalStyle::Positional.eval(&p).score(0) < material);
+        // evalline follows the selected style
+        run(shell, "setoption name EvalStyle value positional").await;
+        let out = run(shell, "evalline e1d1").await;
+        assert!(!out[0].ends_with(" cp 200\n"), "{out:?}");
+        run(shell, "setoption name EvalStyle value material").await;
+        let out = run(shell, "evalline e1d1").await;
+        assert_eq!(out, ["info string evalline e1d1 score cp 200\n"]);
+
+        // piece-square tables: a knight developed to the center beats one on the rim
+        run(shell, "setoption name EvalStyle value psqt").await;
+        run(shell, "position startpos").await;
+        let cp = |out: Vec<String>| -> i32 {
+            out[0].trim().rsplit(' ').next().unwrap().parse().unwrap()
+        };
+        let center = cp(run(shell, "evalline g1f3").await);
+        let rim = cp(run(shell, "evalline g1h3").await);
+        assert!(center > rim, "{center} {rim}");
+    }
+
+    #[tokio::test]
+    async fn setoption_contempt() {
+        let shell = shell();
+        let out = run(shell, "uci").await;
+        let option = "option name Contempt type spin default 0 min 0 max 100\n";
+        assert!(out.contains(&option.to_string()), "{out:?}");
+        run(shell, "setoption name Contempt value 30").await;
+        assert_eq!(shell.config.lock().unwrap().contempt, 30);
+        // only draws from there, worth less than equality to white
+        run(shell, "position fen 7k/8/8/8/8/8/8/6NK w - - 0 1").await;
+        let result = shell.next_result();
+        run(shell, "go depth 2").await;
+        let (_, eval) = result.await.unwrap();
+        assert_eq!(eval.score(0), -30);
+    }
+
+    #[tokio::test]
+    async fn eval_line() {
+        let shell = shell();
+        let out = run(shell, "evalline e2e4 e7e5").await;
+        assert_eq!(out.len(), 2, "{out:?}");
+        for (line, m) in out.iter().zip(["e2e4", "e7e5"]) {
+            let prefix = format!("info string evalline {m} score cp ");
+            let cp: i32 = line.strip_prefix(&prefix).unwrap().trim().parse().unwrap();
+            assert!(cp.abs() < 50, "{line}");
+        }
+        // the current position is left as is
+        assert!(*shell.position.lock().unwrap() == Position::startingpos());
+
+        // stops at the first illegal move
+        let out = run(shell, "evalline e2e4 e2e4 e7e5").await;
+        assert_eq!(out.len(), 2, "{out:?}");
+        assert!(out[1].contains("illegal move"), "{out:?}");
+    }
+
+    #[tokio::test]
+    async fn print_board_footer() {
+        let shell = shell();
+        run(shell, "position startpos moves g1f3 g8f6 b1c3 b8c6").await;
+        let out = run(shell, "d").await;
+        let has = |l: &str| out.iter().any(|x| x.contains(l));
+        assert!(has("fifty_mv: 4"), "{out:?}");
+        assert!(has("fullmove: 3"), "{out:?}");
+        assert!(has("en_passant: -"), "{out:?}");
+        assert!(has("castling: KQkq"), "{out:?}");
+        assert!(has("key: "), "{out:?}");
+
+        run(shell, "position startpos moves e2e4").await;
+        let out = run(shell, "d").await;
+        assert!(out.iter().any(|l| l.contains("en_passant: e3")), "{out:?}");
+
+        // a capture resets the counter
+        run(
+            shell,
+            "position startpos moves g1f3 g8f6 b1c3 b8c6 f3g5 f6e4 c3e4",
+        )
+        .await;
+        let out = run(shell, "d").await;
+        assert!(out.iter().any(|l| l.contains("fifty_mv: 0")), "{out:?}");
+    }
+}